@@ -148,7 +148,7 @@ async fn main(spawner: Spawner) {
         .full_name("HelloRust")
         .build();
 
-    static SCAN_DATA: [u8; 0] = [];
+    static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new().build();
 
     loop {
         let config = peripheral::Config::default();
@@ -233,7 +233,7 @@ async fn main(spawner: Spawner) -> ! {
         .full_name("HelloRust")
         .build();
 
-    static SCAN_DATA: [u8; 0] = [];
+    static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new().build();
 
     static BONDER: StaticCell<Bonder> = StaticCell::new();
     let bonder = BONDER.init(Bonder::default());
@@ -129,7 +129,7 @@ async fn main(spawner: Spawner) {
         .short_name("H")
         .build();
 
-    static SCAN_DATA: [u8; 0] = [];
+    static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new().build();
 
     let l = l2cap::L2cap::<Packet>::init(sd);
 
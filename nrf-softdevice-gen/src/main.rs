@@ -126,6 +126,7 @@ fn main() {
             .collect::<Vec<&str>>();
 
         writeln!(&mut res, "    let ret: u32;",).unwrap();
+        writeln!(&mut res, "    #[cfg(target_arch = \"arm\")]").unwrap();
         writeln!(&mut res, "    core::arch::asm!(\"svc {}\",", num).unwrap();
 
         assert!(arg_names.len() <= 4);
@@ -144,6 +145,18 @@ fn main() {
         }
         writeln!(&mut res, "        lateout(\"r12\") _,").unwrap();
         writeln!(&mut res, "    );").unwrap();
+        writeln!(&mut res, "    #[cfg(not(target_arch = \"arm\"))]").unwrap();
+        writeln!(
+            &mut res,
+            "    {{ ret = svc_unsupported({}, &[{}]); }}",
+            num,
+            arg_names
+                .iter()
+                .map(|arg| format!("to_asm({})", arg))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .unwrap();
         writeln!(&mut res, "    ret").unwrap();
         writeln!(&mut res, "}}",).unwrap();
 
@@ -229,6 +242,12 @@ fn to_asm<T: ToAsm>(t: T) -> u32 {
     t.to_asm()
 }
 
+/// Stand-in for softdevice calls when building for the host, e.g. for unit tests.
+#[cfg(not(target_arch = "arm"))]
+fn svc_unsupported(num: u32, _args: &[u32]) -> u32 {
+    panic!("softdevice call {} is only available on ARM targets", num)
+}
+
 impl ToAsm for u32 {
     fn to_asm(self) -> u32 {
         self
@@ -69,6 +69,12 @@ fn to_asm<T: ToAsm>(t: T) -> u32 {
     t.to_asm()
 }
 
+/// Stand-in for softdevice calls when building for the host, e.g. for unit tests.
+#[cfg(not(target_arch = "arm"))]
+fn svc_unsupported(num: u32, _args: &[u32]) -> u32 {
+    panic!("softdevice call {} is only available on ARM targets", num)
+}
+
 impl ToAsm for u32 {
     fn to_asm(self) -> u32 {
         self
@@ -595,6 +601,7 @@ fn bindgen_test_layout_sd_mbr_command_t() {
 #[inline(always)]
 pub unsafe fn sd_mbr_command(param: *mut sd_mbr_command_t) -> u32 {
     let ret: u32;
+    #[cfg(target_arch = "arm")]
     core::arch::asm!("svc 24",
         inout("r0") to_asm(param) => ret,
         lateout("r1") _,
@@ -602,5 +609,9 @@ pub unsafe fn sd_mbr_command(param: *mut sd_mbr_command_t) -> u32 {
         lateout("r3") _,
         lateout("r12") _,
     );
+    #[cfg(not(target_arch = "arm"))]
+    {
+        ret = svc_unsupported(24, &[to_asm(param)]);
+    }
     ret
 }
@@ -1,8 +1,15 @@
 #[cfg(feature = "defmt")]
 use defmt::Format;
+use fixed::types::I30F2;
 
-const LEGACY_PAYLOAD_LEN: usize = 31;
-const EXTENDED_PAYLOAD_LEN: usize = 254;
+use crate::{raw, temperature_celsius, Softdevice};
+
+const LEGACY_PAYLOAD_LEN: usize = raw::BLE_GAP_ADV_SET_DATA_SIZE_MAX as usize;
+#[cfg(any(feature = "s132", feature = "s140"))]
+const EXTENDED_PAYLOAD_LEN: usize = raw::BLE_GAP_ADV_SET_DATA_SIZE_EXTENDED_MAX_SUPPORTED as usize;
+#[cfg(any(feature = "s132", feature = "s140"))]
+const EXTENDED_CONNECTABLE_PAYLOAD_LEN: usize =
+    raw::BLE_GAP_ADV_SET_DATA_SIZE_EXTENDED_CONNECTABLE_MAX_SUPPORTED as usize;
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(Format))]
@@ -195,7 +202,25 @@ impl<const N: usize> AsRef<[u8]> for AdvertisementPayload<N> {
     }
 }
 
-impl<const N: usize> core::ops::Deref for AdvertisementPayload<N> {
+impl core::ops::Deref for LegacyAdvertisementPayload {
+    type Target = LegacyAdvertisementData;
+
+    fn deref(&self) -> &Self::Target {
+        LegacyAdvertisementData::new(&self.buf[..self.len])
+    }
+}
+
+#[cfg(any(feature = "s132", feature = "s140"))]
+impl core::ops::Deref for ExtendedAdvertisementPayload {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf[..self.len]
+    }
+}
+
+#[cfg(any(feature = "s132", feature = "s140"))]
+impl core::ops::Deref for ExtendedConnectableAdvertisementPayload {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
@@ -203,6 +228,52 @@ impl<const N: usize> core::ops::Deref for AdvertisementPayload<N> {
     }
 }
 
+/// Advertising data that fits in a legacy advertising PDU.
+///
+/// Legacy advertisement types only accept this, so that extended payloads are rejected at compile
+/// time. A [`LegacyAdvertisementPayload`] dereferences to it, raw bytes can be wrapped with
+/// [`new`](Self::new).
+#[derive(Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct LegacyAdvertisementData([u8]);
+
+impl LegacyAdvertisementData {
+    /// Wrap raw advertising data.
+    ///
+    /// Panics if `data` is longer than 31 bytes. When called in a const context this is a
+    /// compile-time error.
+    pub const fn new(data: &[u8]) -> &Self {
+        core::assert!(data.len() <= LEGACY_PAYLOAD_LEN, "legacy advertising data too long");
+        // Safety: `LegacyAdvertisementData` is a `repr(transparent)` wrapper around `[u8]`.
+        unsafe { &*(data as *const [u8] as *const Self) }
+    }
+
+    pub const fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl core::ops::Deref for LegacyAdvertisementData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for LegacyAdvertisementData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl Format for LegacyAdvertisementData {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "{=[u8]}", &self.0)
+    }
+}
+
 impl<const K: usize> AdvertisementBuilder<K> {
     pub const fn new() -> Self {
         Self { buf: [0; K], ptr: 0 }
@@ -344,8 +415,336 @@ impl<const K: usize> AdvertisementBuilder<K> {
     }
 }
 
+const EDDYSTONE_UUID: [u8; 2] = 0xfeaau16.to_le_bytes();
+
+// Maximum length of the encoded URL of an Eddystone-URL frame, so that it fits in a legacy advertisement.
+const EDDYSTONE_URL_MAX_LEN: usize = 17;
+
+// Eddystone-URL scheme prefixes, the index is the encoded value.
+const EDDYSTONE_URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+// Eddystone-URL expansions, the index is the encoded value. Those with a trailing
+// slash come first so they take precedence.
+const EDDYSTONE_URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net", ".info", ".biz",
+    ".gov",
+];
+
+const fn matches_at(bytes: &[u8], at: usize, pattern: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    if at + pattern.len() > bytes.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < pattern.len() {
+        if bytes[at + i] != pattern[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Beacon formats.
+///
+/// These only add the beacon's own AD structures, flags must be added separately if needed.
+impl<const K: usize> AdvertisementBuilder<K> {
+    /// Add an Apple iBeacon.
+    ///
+    /// `uuid` is in big-endian format, as UUIDs are normally written. `measured_power` is the RSSI
+    /// at 1m, in dBm.
+    pub const fn ibeacon(self, uuid: [u8; 16], major: u16, minor: u16, measured_power: i8) -> Self {
+        let major = major.to_be_bytes();
+        let minor = minor.to_be_bytes();
+        self.write(&[
+            0x1a,
+            AdvertisementDataType::MANUFACTURER_SPECIFIC_DATA.to_u8(),
+            0x4c,
+            0x00,
+            0x02,
+            0x15,
+        ])
+        .write(&uuid)
+        .write(&[major[0], major[1], minor[0], minor[1], measured_power as u8])
+    }
+
+    /// Add an AltBeacon.
+    ///
+    /// `reference_rssi` is the RSSI at 1m, in dBm.
+    pub const fn altbeacon(self, company_id: u16, beacon_id: [u8; 20], reference_rssi: i8, reserved: u8) -> Self {
+        let company_id = company_id.to_le_bytes();
+        self.write(&[
+            0x1b,
+            AdvertisementDataType::MANUFACTURER_SPECIFIC_DATA.to_u8(),
+            company_id[0],
+            company_id[1],
+            0xbe,
+            0xac,
+        ])
+        .write(&beacon_id)
+        .write(&[reference_rssi as u8, reserved])
+    }
+
+    // Eddystone service list and the header of the service data, followed by `frame_len` bytes of frame.
+    const fn eddystone(self, frame_len: usize) -> Self {
+        self.write(&[
+            3,
+            AdvertisementDataType::COMPLETE_16_SERVICE_LIST.to_u8(),
+            EDDYSTONE_UUID[0],
+            EDDYSTONE_UUID[1],
+        ])
+        .write(&[
+            frame_len as u8 + 3,
+            AdvertisementDataType::SERVICE_DATA_16.to_u8(),
+            EDDYSTONE_UUID[0],
+            EDDYSTONE_UUID[1],
+        ])
+    }
+
+    /// Add an Eddystone-UID frame.
+    ///
+    /// `tx_power` is the RSSI at 0m, in dBm.
+    pub const fn eddystone_uid(self, tx_power: i8, namespace: [u8; 10], instance: [u8; 6]) -> Self {
+        self.eddystone(20)
+            .write(&[0x00, tx_power as u8])
+            .write(&namespace)
+            .write(&instance)
+            .write(&[0, 0])
+    }
+
+    /// Add an Eddystone-URL frame.
+    ///
+    /// `tx_power` is the RSSI at 0m, in dBm. The scheme and common domain suffixes of `url` are
+    /// compressed.
+    ///
+    /// Panics if `url` doesn't start with `http://` or `https://`, or if the compressed URL is longer
+    /// than 17 bytes, the most that fits in a legacy advertisement. When called in a const context
+    /// this is a compile-time error.
+    pub const fn eddystone_url(self, tx_power: i8, url: &str) -> Self {
+        let bytes = url.as_bytes();
+
+        let mut scheme = 0;
+        while !matches_at(bytes, 0, EDDYSTONE_URL_SCHEMES[scheme]) {
+            scheme += 1;
+            core::assert!(
+                scheme < EDDYSTONE_URL_SCHEMES.len(),
+                "URL must start with http:// or https://"
+            );
+        }
+        let start = EDDYSTONE_URL_SCHEMES[scheme].len();
+
+        // Compress into a buffer first, the length goes before the data.
+        let mut encoded = [0u8; 255];
+        let mut len = 0;
+        let mut i = start;
+        while i < bytes.len() && len < encoded.len() {
+            let mut expansion = 0;
+            while expansion < EDDYSTONE_URL_EXPANSIONS.len()
+                && !matches_at(bytes, i, EDDYSTONE_URL_EXPANSIONS[expansion])
+            {
+                expansion += 1;
+            }
+            if expansion < EDDYSTONE_URL_EXPANSIONS.len() {
+                encoded[len] = expansion as u8;
+                i += EDDYSTONE_URL_EXPANSIONS[expansion].len();
+            } else {
+                encoded[len] = bytes[i];
+                i += 1;
+            }
+            len += 1;
+        }
+        core::assert!(
+            len <= EDDYSTONE_URL_MAX_LEN && i >= bytes.len(),
+            "Eddystone-URL longer than 17 bytes once compressed"
+        );
+
+        let (encoded, _) = encoded.split_at(len);
+        self.eddystone(3 + len)
+            .write(&[0x10, tx_power as u8, scheme as u8])
+            .write(encoded)
+    }
+
+    /// Add an unencrypted Eddystone-TLM frame.
+    ///
+    /// `battery_mv` is the battery voltage in mV, 0 if not supported. `temperature` is in Celsius,
+    /// `None` if not supported, see also [`eddystone_tlm_measured`](Self::eddystone_tlm_measured).
+    /// `adv_count` is the number of advertising PDUs sent since power-up, and `uptime` the time
+    /// since power-up, in 100ms units.
+    pub const fn eddystone_tlm(self, battery_mv: u16, temperature: Option<I30F2>, adv_count: u32, uptime: u32) -> Self {
+        // 8.8 fixed point, 0x8000 (-128.0) if not supported.
+        let temperature = match temperature {
+            Some(t) => {
+                // I30F2 to 8.8, saturating.
+                let bits = t.to_bits();
+                if bits > (i16::MAX >> 6) as i32 {
+                    i16::MAX
+                } else if bits < (i16::MIN >> 6) as i32 {
+                    i16::MIN
+                } else {
+                    (bits << 6) as i16
+                }
+            }
+            None => i16::MIN,
+        };
+
+        self.eddystone(14)
+            .write(&[0x20, 0x00])
+            .write(&battery_mv.to_be_bytes())
+            .write(&temperature.to_be_bytes())
+            .write(&adv_count.to_be_bytes())
+            .write(&uptime.to_be_bytes())
+    }
+
+    /// Add an unencrypted Eddystone-TLM frame, with the temperature measured by the softdevice.
+    ///
+    /// See [`eddystone_tlm`](Self::eddystone_tlm) for the other parameters.
+    pub fn eddystone_tlm_measured(self, sd: &Softdevice, battery_mv: u16, adv_count: u32, uptime: u32) -> Self {
+        self.eddystone_tlm(battery_mv, temperature_celsius(sd).ok(), adv_count, uptime)
+    }
+
+    /// Add an Eddystone-EID frame.
+    ///
+    /// `tx_power` is the RSSI at 0m, in dBm.
+    pub const fn eddystone_eid(self, tx_power: i8, eid: [u8; 8]) -> Self {
+        self.eddystone(10).write(&[0x30, tx_power as u8]).write(&eid)
+    }
+}
+
+/// Builder for legacy advertising and scan response data, up to 31 bytes.
 pub type LegacyAdvertisementBuilder = AdvertisementBuilder<LEGACY_PAYLOAD_LEN>;
+/// Builder for non-connectable extended advertising data, up to 255 bytes.
+///
+/// The softdevice fragments data that doesn't fit in a single PDU into a chain of `AUX_CHAIN_IND`.
+#[cfg(any(feature = "s132", feature = "s140"))]
 pub type ExtendedAdvertisementBuilder = AdvertisementBuilder<EXTENDED_PAYLOAD_LEN>;
+/// Builder for connectable extended advertising data, up to 238 bytes.
+///
+/// Connectable extended advertising can't be fragmented, so the data must fit in a single `AUX_ADV_IND`.
+#[cfg(any(feature = "s132", feature = "s140"))]
+pub type ExtendedConnectableAdvertisementBuilder = AdvertisementBuilder<EXTENDED_CONNECTABLE_PAYLOAD_LEN>;
 
 pub type LegacyAdvertisementPayload = AdvertisementPayload<LEGACY_PAYLOAD_LEN>;
+#[cfg(any(feature = "s132", feature = "s140"))]
 pub type ExtendedAdvertisementPayload = AdvertisementPayload<EXTENDED_PAYLOAD_LEN>;
+#[cfg(any(feature = "s132", feature = "s140"))]
+pub type ExtendedConnectableAdvertisementPayload = AdvertisementPayload<EXTENDED_CONNECTABLE_PAYLOAD_LEN>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build<const K: usize>(builder: AdvertisementBuilder<K>) -> heapless::Vec<u8, K> {
+        unwrap!(heapless::Vec::from_slice(builder.build().as_ref()))
+    }
+
+    #[test]
+    fn ibeacon() {
+        let uuid = [
+            0xe2, 0xc5, 0x6d, 0xb5, 0xdf, 0xfb, 0x48, 0xd2, 0xb0, 0x60, 0xd0, 0xf5, 0xa7, 0x10, 0x96, 0xe0,
+        ];
+        let data = LegacyAdvertisementBuilder::new()
+            .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
+            .ibeacon(uuid, 0x0102, 0x0304, -59);
+        assert_eq!(
+            build(data),
+            [
+                0x02, 0x01, 0x06, 0x1a, 0xff, 0x4c, 0x00, 0x02, 0x15, 0xe2, 0xc5, 0x6d, 0xb5, 0xdf, 0xfb, 0x48, 0xd2,
+                0xb0, 0x60, 0xd0, 0xf5, 0xa7, 0x10, 0x96, 0xe0, 0x01, 0x02, 0x03, 0x04, 0xc5,
+            ]
+        );
+    }
+
+    #[test]
+    fn altbeacon() {
+        // Example from the AltBeacon specification.
+        let beacon_id = [
+            0x2f, 0x23, 0x44, 0x54, 0xcf, 0x6d, 0x4a, 0x0f, 0xad, 0xf2, 0xf4, 0x91, 0x1b, 0xa9, 0xff, 0xa6, 0x00, 0x01,
+            0x00, 0x02,
+        ];
+        let data = LegacyAdvertisementBuilder::new().altbeacon(0x0118, beacon_id, -59, 0x00);
+        assert_eq!(
+            build(data),
+            [
+                0x1b, 0xff, 0x18, 0x01, 0xbe, 0xac, 0x2f, 0x23, 0x44, 0x54, 0xcf, 0x6d, 0x4a, 0x0f, 0xad, 0xf2, 0xf4,
+                0x91, 0x1b, 0xa9, 0xff, 0xa6, 0x00, 0x01, 0x00, 0x02, 0xc5, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn eddystone_uid() {
+        let namespace = [0x8b, 0x0c, 0xa7, 0x50, 0xe7, 0xa7, 0x4e, 0x14, 0xbd, 0x99];
+        let instance = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let data = LegacyAdvertisementBuilder::new().eddystone_uid(-18, namespace, instance);
+        assert_eq!(
+            build(data),
+            [
+                0x03, 0x03, 0xaa, 0xfe, 0x17, 0x16, 0xaa, 0xfe, 0x00, 0xee, 0x8b, 0x0c, 0xa7, 0x50, 0xe7, 0xa7, 0x4e,
+                0x14, 0xbd, 0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn eddystone_url() {
+        let data = LegacyAdvertisementBuilder::new().eddystone_url(-20, "http://www.google.com/");
+        assert_eq!(
+            build(data),
+            [
+                0x03, 0x03, 0xaa, 0xfe, 0x0d, 0x16, 0xaa, 0xfe, 0x10, 0xec, 0x00, b'g', b'o', b'o', b'g', b'l', b'e',
+                0x00,
+            ]
+        );
+
+        // Expansions apply anywhere, even within a word, and ".com" also without a slash.
+        let data = LegacyAdvertisementBuilder::new().eddystone_url(0, "https://a.company.com");
+        assert_eq!(
+            build(data),
+            [
+                0x03, 0x03, 0xaa, 0xfe, 0x0d, 0x16, 0xaa, 0xfe, 0x10, 0x00, 0x03, b'a', 0x07, b'p', b'a', b'n', b'y',
+                0x07,
+            ]
+        );
+    }
+
+    #[test]
+    fn eddystone_url_max_len() {
+        // 17 bytes once compressed.
+        let data = LegacyAdvertisementBuilder::new().eddystone_url(0, "https://goo.gl/abcdefghi.com/");
+        assert_eq!(build(data).len(), 28);
+    }
+
+    #[test]
+    #[should_panic(expected = "Eddystone-URL longer than 17 bytes once compressed")]
+    fn eddystone_url_too_long() {
+        let _ = AdvertisementBuilder::<64>::new().eddystone_url(0, "https://goo.gl/abcdefghijk.com/");
+    }
+
+    #[test]
+    fn eddystone_tlm() {
+        let data = LegacyAdvertisementBuilder::new().eddystone_tlm(3000, Some(I30F2::from_num(25.5)), 0x0102_0304, 600);
+        assert_eq!(
+            build(data),
+            [
+                0x03, 0x03, 0xaa, 0xfe, 0x11, 0x16, 0xaa, 0xfe, 0x20, 0x00, 0x0b, 0xb8, 0x19, 0x80, 0x01, 0x02, 0x03,
+                0x04, 0x00, 0x00, 0x02, 0x58,
+            ]
+        );
+
+        let data = LegacyAdvertisementBuilder::new().eddystone_tlm(0, None, 0, 0);
+        assert_eq!(&build(data)[10..14], [0x00, 0x00, 0x80, 0x00]);
+
+        let data = LegacyAdvertisementBuilder::new().eddystone_tlm(0, Some(I30F2::from_num(-10.25)), 0, 0);
+        assert_eq!(&build(data)[12..14], [0xf5, 0xc0]);
+    }
+
+    #[test]
+    fn eddystone_eid() {
+        let eid = [1, 2, 3, 4, 5, 6, 7, 8];
+        let data = LegacyAdvertisementBuilder::new().eddystone_eid(-4, eid);
+        assert_eq!(
+            build(data),
+            [0x03, 0x03, 0xaa, 0xfe, 0x0d, 0x16, 0xaa, 0xfe, 0x30, 0xfc, 1, 2, 3, 4, 5, 6, 7, 8]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Parsing of advertising data into AD structures.
+//!
+//! This is the counterpart of [`advertisement_builder`](crate::ble::advertisement_builder). It only
+//! works on byte slices, and doesn't touch the softdevice.
+
+#[cfg(feature = "defmt")]
+use defmt::Format;
+
+use crate::ble::advertisement_builder::{AdvertisementDataType, ServiceList, ServiceUuid16};
+use crate::ble::Appearance;
+
+/// A single AD structure of advertising or scan response data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum AdStructure<'a> {
+    /// Flags, see [`Flag`](crate::ble::advertisement_builder::Flag).
+    Flags(u8),
+    ServiceUuids16(ServiceList, ServiceUuids<'a, 2>),
+    ServiceUuids32(ServiceList, ServiceUuids<'a, 4>),
+    ServiceUuids128(ServiceList, ServiceUuids<'a, 16>),
+    /// Local name. Shortened names may have been cut in the middle of a UTF-8 sequence,
+    /// in that case only the valid prefix is returned.
+    LocalName {
+        complete: bool,
+        name: &'a str,
+    },
+    ManufacturerData {
+        company_id: u16,
+        data: &'a [u8],
+    },
+    ServiceData16 {
+        uuid: ServiceUuid16,
+        data: &'a [u8],
+    },
+    ServiceData32 {
+        uuid: u32,
+        data: &'a [u8],
+    },
+    /// Service data for a 128-bit UUID. The UUID is in little-endian format.
+    ServiceData128 {
+        uuid: [u8; 16],
+        data: &'a [u8],
+    },
+    Appearance(Appearance),
+    /// TX power level in dBm.
+    TxPower(i8),
+    /// Any other AD structure, or one that is too short for its type.
+    Unknown {
+        ad_type: AdvertisementDataType,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> AdStructure<'a> {
+    /// Whether this is a list of service UUIDs that contains `uuid`.
+    pub fn lists_service(&self, uuid: &ServiceUuid) -> bool {
+        match (self, uuid) {
+            (AdStructure::ServiceUuids16(_, list), ServiceUuid::Uuid16(uuid)) => list.contains(*uuid),
+            (AdStructure::ServiceUuids32(_, list), ServiceUuid::Uuid32(uuid)) => list.contains(*uuid),
+            (AdStructure::ServiceUuids128(_, list), ServiceUuid::Uuid128(uuid)) => list.contains(uuid),
+            _ => false,
+        }
+    }
+}
+
+/// A service UUID of any size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum ServiceUuid {
+    Uuid16(ServiceUuid16),
+    Uuid32(u32),
+    /// 128-bit UUID, in little-endian format.
+    Uuid128([u8; 16]),
+}
+
+/// List of service UUIDs of `N` bytes each, in an [`AdStructure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct ServiceUuids<'a, const N: usize>(&'a [u8]);
+
+impl<'a, const N: usize> ServiceUuids<'a, N> {
+    /// The raw UUIDs, concatenated in little-endian format.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Iterate over the UUIDs as little-endian byte arrays.
+    pub fn iter(&self) -> impl Iterator<Item = [u8; N]> + 'a {
+        self.0.chunks_exact(N).map(|c| unwrap!(c.try_into()))
+    }
+}
+
+impl<'a> ServiceUuids<'a, 2> {
+    pub fn contains(&self, uuid: ServiceUuid16) -> bool {
+        self.iter().any(|u| u16::from_le_bytes(u) == uuid.to_u16())
+    }
+}
+
+impl<'a> ServiceUuids<'a, 4> {
+    pub fn contains(&self, uuid: u32) -> bool {
+        self.iter().any(|u| u32::from_le_bytes(u) == uuid)
+    }
+}
+
+impl<'a> ServiceUuids<'a, 16> {
+    /// `uuid` must be in little-endian format.
+    pub fn contains(&self, uuid: &[u8; 16]) -> bool {
+        self.iter().any(|u| &u == uuid)
+    }
+}
+
+/// Iterator over the AD structures of advertising data.
+///
+/// Iteration stops at the first malformed structure or at zero padding.
+#[derive(Debug, Clone)]
+pub struct AdStructures<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AdStructures<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for AdStructures<'a> {
+    type Item = AdStructure<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&len, rest) = self.data.split_first()?;
+        let len = len as usize;
+        if len == 0 || len > rest.len() {
+            self.data = &[];
+            return None;
+        }
+
+        let (structure, rest) = rest.split_at(len);
+        self.data = rest;
+        Some(parse(AdvertisementDataType::from_u8(structure[0]), &structure[1..]))
+    }
+}
+
+fn list(ad_type: AdvertisementDataType, complete: AdvertisementDataType) -> ServiceList {
+    if ad_type == complete {
+        ServiceList::Complete
+    } else {
+        ServiceList::Incomplete
+    }
+}
+
+fn parse(ad_type: AdvertisementDataType, data: &[u8]) -> AdStructure<'_> {
+    type T = AdvertisementDataType;
+
+    let res = match ad_type {
+        T::FLAGS if data.len() == 1 => Some(AdStructure::Flags(data[0])),
+        T::INCOMPLETE_16_SERVICE_LIST | T::COMPLETE_16_SERVICE_LIST if data.len() % 2 == 0 => Some(
+            AdStructure::ServiceUuids16(list(ad_type, T::COMPLETE_16_SERVICE_LIST), ServiceUuids(data)),
+        ),
+        T::INCOMPLETE_32_SERVICE_LIST | T::COMPLETE_32_SERVICE_LIST if data.len() % 4 == 0 => Some(
+            AdStructure::ServiceUuids32(list(ad_type, T::COMPLETE_32_SERVICE_LIST), ServiceUuids(data)),
+        ),
+        T::INCOMPLETE_128_SERVICE_LIST | T::COMPLETE_128_SERVICE_LIST if data.len() % 16 == 0 => Some(
+            AdStructure::ServiceUuids128(list(ad_type, T::COMPLETE_128_SERVICE_LIST), ServiceUuids(data)),
+        ),
+        T::SHORT_NAME | T::FULL_NAME => {
+            let name = match core::str::from_utf8(data) {
+                Ok(name) => name,
+                Err(e) => core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default(),
+            };
+            Some(AdStructure::LocalName {
+                complete: ad_type == T::FULL_NAME,
+                name,
+            })
+        }
+        T::MANUFACTURER_SPECIFIC_DATA if data.len() >= 2 => Some(AdStructure::ManufacturerData {
+            company_id: u16::from_le_bytes([data[0], data[1]]),
+            data: &data[2..],
+        }),
+        T::SERVICE_DATA_16 if data.len() >= 2 => Some(AdStructure::ServiceData16 {
+            uuid: ServiceUuid16::from_u16(u16::from_le_bytes([data[0], data[1]])),
+            data: &data[2..],
+        }),
+        T::SERVICE_DATA_32 if data.len() >= 4 => Some(AdStructure::ServiceData32 {
+            uuid: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            data: &data[4..],
+        }),
+        T::SERVICE_DATA_128 if data.len() >= 16 => Some(AdStructure::ServiceData128 {
+            uuid: unwrap!(data[..16].try_into()),
+            data: &data[16..],
+        }),
+        T::APPEARANCE if data.len() == 2 => Some(AdStructure::Appearance(Appearance::from_u16(u16::from_le_bytes([
+            data[0], data[1],
+        ])))),
+        T::TXPOWER_LEVEL if data.len() == 1 => Some(AdStructure::TxPower(data[0] as i8)),
+        _ => None,
+    };
+
+    res.unwrap_or(AdStructure::Unknown { ad_type, data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(data: &[u8]) -> heapless::Vec<AdStructure<'_>, 8> {
+        AdStructures::new(data).collect()
+    }
+
+    #[test]
+    fn truncated_structure() {
+        // The manufacturer data claims 5 bytes but only 2 follow.
+        let data = [0x02, 0x01, 0x06, 0x05, 0xff, 0x59];
+        assert_eq!(parse_all(&data), [AdStructure::Flags(0x06)]);
+    }
+
+    #[test]
+    fn zero_length_padding() {
+        let data = [0x02, 0x01, 0x06, 0x00, 0x02, 0x0a, 0x04];
+        assert_eq!(parse_all(&data), [AdStructure::Flags(0x06)]);
+        assert_eq!(parse_all(&[0x00; 31]), []);
+        assert_eq!(parse_all(&[]), []);
+    }
+
+    #[test]
+    fn type_only_structure() {
+        let data = [0x01, 0x09, 0x02, 0x0a, 0xfc];
+        assert_eq!(
+            parse_all(&data),
+            [
+                AdStructure::LocalName {
+                    complete: true,
+                    name: ""
+                },
+                AdStructure::TxPower(-4),
+            ]
+        );
+    }
+
+    #[test]
+    fn uuid16_list() {
+        let data = [0x05, 0x03, 0x0f, 0x18, 0x0d, 0x18];
+        let ads = parse_all(&data);
+        let AdStructure::ServiceUuids16(ServiceList::Complete, list) = ads[0] else {
+            panic!("{:?}", ads);
+        };
+        assert!(list.contains(ServiceUuid16::BATTERY));
+        assert!(list.contains(ServiceUuid16::HEART_RATE));
+        assert!(!list.contains(ServiceUuid16::GLUCOSE));
+        assert!(ads[0].lists_service(&ServiceUuid::Uuid16(ServiceUuid16::HEART_RATE)));
+        assert!(!ads[0].lists_service(&ServiceUuid::Uuid32(0x180d)));
+    }
+
+    #[test]
+    fn uuid32_list() {
+        let data = [0x05, 0x04, 0x78, 0x56, 0x34, 0x12];
+        let ads = parse_all(&data);
+        let AdStructure::ServiceUuids32(ServiceList::Incomplete, list) = ads[0] else {
+            panic!("{:?}", ads);
+        };
+        assert!(list.contains(0x1234_5678));
+        assert!(ads[0].lists_service(&ServiceUuid::Uuid32(0x1234_5678)));
+    }
+
+    #[test]
+    fn uuid128_list() {
+        let uuid = [
+            0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0, 0x93, 0xf3, 0xa3, 0xb5, 0x01, 0x00, 0x40, 0x6e,
+        ];
+        let mut data = [0; 18];
+        data[0] = 17;
+        data[1] = 0x07;
+        data[2..].copy_from_slice(&uuid);
+        let ads = parse_all(&data);
+        let AdStructure::ServiceUuids128(ServiceList::Complete, list) = ads[0] else {
+            panic!("{:?}", ads);
+        };
+        assert_eq!(list.as_bytes(), uuid);
+        assert!(list.contains(&uuid));
+        assert!(ads[0].lists_service(&ServiceUuid::Uuid128(uuid)));
+    }
+
+    #[test]
+    fn uuid_list_odd_length() {
+        let data = [0x04, 0x02, 0x0f, 0x18, 0x0d];
+        assert_eq!(
+            parse_all(&data),
+            [AdStructure::Unknown {
+                ad_type: AdvertisementDataType::INCOMPLETE_16_SERVICE_LIST,
+                data: &[0x0f, 0x18, 0x0d],
+            }]
+        );
+    }
+
+    #[test]
+    fn local_name() {
+        let data = [0x05, 0x09, b'n', b'R', b'F', b'!', 0x04, 0x08, b'a', 0xc3, 0xa9];
+        assert_eq!(
+            parse_all(&data),
+            [
+                AdStructure::LocalName {
+                    complete: true,
+                    name: "nRF!"
+                },
+                AdStructure::LocalName {
+                    complete: false,
+                    name: "a\u{e9}"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn shortened_name_cut_in_utf8_sequence() {
+        let data = [0x04, 0x08, b'a', b'b', 0xc3];
+        assert_eq!(
+            parse_all(&data),
+            [AdStructure::LocalName {
+                complete: false,
+                name: "ab"
+            }]
+        );
+    }
+}
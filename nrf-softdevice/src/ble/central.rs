@@ -5,13 +5,16 @@
 
 use core::{mem, ptr};
 
+use crate::ble::advertisement_parser::AdStructures;
 use crate::ble::types::*;
 use crate::ble::{Address, Connection, OutOfConnsError};
 use crate::util::{get_union_field, OnDrop, Portal};
-use crate::{raw, RawError, Softdevice};
+use crate::{raw, ConnProfileId, RawError, Softdevice};
 
 #[cfg(feature = "ble-gatt-client")]
 use crate::ble::gatt_client::MtuExchangeError;
+#[cfg(feature = "ble-scanner")]
+pub use crate::ble::scanner::{start_discovery, DiscoveredDevice, Discovery};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -19,6 +22,10 @@ pub enum ConnectError {
     Timeout,
     NoAddresses,
     NoFreeConn,
+    /// The scan phys are invalid, see [`ScanError::InvalidPhy`]. On s140 this is also returned when
+    /// connecting on the coded phy with a connection profile whose event length is too short for it,
+    /// see [`ConnProfile::long_range`](crate::ConnProfile::long_range).
+    InvalidPhy,
     #[cfg(feature = "ble-gatt-client")]
     MtuExchange(MtuExchangeError),
     Raw(RawError),
@@ -56,7 +63,22 @@ pub async fn connect_with_security(
 }
 
 // Begins an ATT MTU exchange procedure, followed by a data length update request as necessary.
-async fn connect_inner<F>(_sd: &Softdevice, config: &ConnectConfig<'_>, new_conn: F) -> Result<Connection, ConnectError>
+async fn connect_inner<F>(sd: &Softdevice, config: &ConnectConfig<'_>, new_conn: F) -> Result<Connection, ConnectError>
+where
+    F: Fn(u16, Role, Address, raw::ble_gap_conn_params_t) -> Result<Connection, OutOfConnsError>,
+{
+    let conn = establish(sd, config, new_conn).await?;
+
+    #[cfg(feature = "ble-gatt-client")]
+    {
+        let mtu = config.att_mtu.unwrap_or(sd.att_mtu_for(config.conn_profile));
+        crate::ble::gatt_client::att_mtu_exchange(&conn, mtu).await?;
+    }
+
+    Ok(conn)
+}
+
+async fn establish<F>(sd: &Softdevice, config: &ConnectConfig<'_>, new_conn: F) -> Result<Connection, ConnectError>
 where
     F: Fn(u16, Role, Address, raw::ble_gap_conn_params_t) -> Result<Connection, OutOfConnsError>,
 {
@@ -68,6 +90,16 @@ where
         return Err(ConnectError::NoAddresses);
     }
 
+    if !config.scan_config.phys_valid() {
+        return Err(ConnectError::InvalidPhy);
+    }
+    #[cfg(feature = "s140")]
+    if config.scan_config.phys.has_coded() && !sd.supports_coded_phy(config.conn_profile) {
+        warn!("connection profile event length too short for the coded phy");
+        return Err(ConnectError::InvalidPhy);
+    }
+    let _ = sd;
+
     let scan_params = config.scan_config.to_raw()?;
 
     let d = OnDrop::new(|| {
@@ -77,7 +109,14 @@ where
         }
     });
 
-    let ret = unsafe { raw::sd_ble_gap_connect(ptr::null(), &scan_params, &config.conn_params, 1) };
+    let ret = unsafe {
+        raw::sd_ble_gap_connect(
+            ptr::null(),
+            &scan_params,
+            &config.conn_params,
+            config.conn_profile.conn_cfg_tag(),
+        )
+    };
     if let Err(err) = RawError::convert(ret) {
         warn!("sd_ble_gap_connect err {:?}", err);
         return Err(err.into());
@@ -98,7 +137,17 @@ where
                     debug!("connected role={:?} peer_addr={:?}", role, peer_address);
 
                     match new_conn(conn_handle, role, peer_address, conn_params) {
-                        Ok(conn) => Ok(conn),
+                        Ok(conn) => {
+                            conn.with_state(|state| {
+                                state.conn_profile = config.conn_profile;
+                                // The connection inherits the TX power of the initiator.
+                                state.tx_power = config.scan_config.tx_power;
+                                let phy = config.scan_config.conn_phy();
+                                state.tx_phy = phy as u8;
+                                state.rx_phy = phy as u8;
+                            });
+                            Ok(conn)
+                        }
                         Err(_) => {
                             raw::sd_ble_gap_disconnect(
                                 conn_handle,
@@ -116,13 +165,117 @@ where
 
     d.defuse();
 
-    #[cfg(feature = "ble-gatt-client")]
-    {
-        let mtu = config.att_mtu.unwrap_or(_sd.att_mtu);
-        crate::ble::gatt_client::att_mtu_exchange(&conn, mtu).await?;
+    Ok(conn)
+}
+
+/// Configuration of [`connect_matching`].
+#[cfg(feature = "ble-scanner")]
+#[derive(Copy, Clone)]
+pub struct ConnectMatchingConfig<'a> {
+    /// `connect.scan_config.whitelist` is optional. If set, only whitelisted advertisers are
+    /// considered, the connection itself is then made with a whitelist of only the chosen advertiser.
+    pub connect: ConnectConfig<'a>,
+    /// Fail with [`ConnectError::Timeout`] if no connection is made within this.
+    pub timeout: embassy_time::Duration,
+    /// Without the `ble-gatt-client` feature, wait for the peer to respond on the new link before
+    /// returning, see [`connect_matching`].
+    pub wait_established: bool,
+}
+
+#[cfg(feature = "ble-scanner")]
+impl<'a> Default for ConnectMatchingConfig<'a> {
+    fn default() -> Self {
+        Self {
+            connect: ConnectConfig::default(),
+            timeout: embassy_time::Duration::from_secs(10),
+            wait_established: true,
+        }
     }
+}
 
-    Ok(conn)
+/// Scan for a connectable advertiser for which `f` returns true, and connect to it.
+///
+/// A connection can fail with [`HciStatus::CONN_FAILED_TO_BE_ESTABLISHED`] right after being
+/// made if the peer never responds, scanning starts again then. With the `ble-gatt-client`
+/// feature, the ATT MTU exchange done as in [`connect`] confirms the peer responded. Otherwise,
+/// with `wait_established` this waits for the 6 connection intervals the peer has to respond in,
+/// and without it this returns as soon as the link is made, so the connection may still fail
+/// afterwards.
+#[cfg(feature = "ble-scanner")]
+pub async fn connect_matching<F>(
+    sd: &Softdevice,
+    config: &ConnectMatchingConfig<'_>,
+    mut f: F,
+) -> Result<Connection, ConnectError>
+where
+    F: FnMut(&AdvertisementReport) -> bool,
+{
+    use embassy_futures::select::{select, Either};
+    use embassy_time::Timer;
+
+    let timeout = config.timeout;
+    #[cfg(not(feature = "ble-gatt-client"))]
+    let confirm = config.wait_established;
+    let config = &config.connect;
+    let attempts = async {
+        loop {
+            let res = scan(sd, &config.scan_config, |report| {
+                if report.type_.connectable() == 0 {
+                    return None;
+                }
+                // Safety: the report comes from a softdevice event.
+                let report = unsafe { AdvertisementReport::from_raw(report) };
+                let address = report.address();
+                let whitelisted = match config.scan_config.whitelist {
+                    Some(whitelist) => whitelist.iter().any(|a| **a == address),
+                    None => true,
+                };
+                (whitelisted && f(&report)).then_some(address)
+            })
+            .await;
+            let address = match res {
+                Ok(address) => address,
+                Err(ScanError::Timeout) => return Err(ConnectError::Timeout),
+                Err(ScanError::InvalidPhy) => return Err(ConnectError::InvalidPhy),
+                Err(ScanError::Raw(err)) => return Err(ConnectError::Raw(err)),
+            };
+
+            debug!("connect_matching: connecting to {:?}", address);
+            let whitelist = [&address];
+            let mut connect_config = *config;
+            connect_config.scan_config.whitelist = Some(&whitelist);
+            let conn = establish(sd, &connect_config, Connection::new).await?;
+
+            #[cfg(feature = "ble-gatt-client")]
+            let res = {
+                let mtu = config.att_mtu.unwrap_or(sd.att_mtu_for(config.conn_profile));
+                crate::ble::gatt_client::att_mtu_exchange(&conn, mtu)
+                    .await
+                    .map_err(ConnectError::from)
+            };
+            #[cfg(not(feature = "ble-gatt-client"))]
+            let res = {
+                if confirm {
+                    let interval_us = u64::from(conn.conn_params().max_conn_interval) * 1250;
+                    let window = embassy_time::Duration::from_micros(interval_us * 6);
+                    let _ = select(conn.disconnected(), Timer::after(window)).await;
+                }
+                Ok::<(), ConnectError>(())
+            };
+
+            if conn.disconnect_reason() == Some(HciStatus::CONN_FAILED_TO_BE_ESTABLISHED) {
+                debug!("connect_matching: connection failed to be established, retrying");
+                continue;
+            }
+            res?;
+            return Ok(conn);
+        }
+    };
+
+    match select(Timer::after(timeout), attempts).await {
+        Either::First(()) => Err(ConnectError::Timeout),
+        Either::Second(res) => res,
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -133,6 +286,8 @@ pub struct ConnectConfig<'a> {
 
     pub scan_config: ScanConfig<'a>,
     pub conn_params: raw::ble_gap_conn_params_t,
+    /// Connection profile to use, see [`Config::conn_profiles`](crate::Config::conn_profiles).
+    pub conn_profile: ConnProfileId,
 }
 
 impl<'a> Default for ConnectConfig<'a> {
@@ -147,14 +302,36 @@ impl<'a> Default for ConnectConfig<'a> {
                 slave_latency: 0,
                 conn_sup_timeout: 400, // 4s
             },
+            conn_profile: ConnProfileId::DEFAULT,
         }
     }
 }
 
+#[cfg(feature = "s140")]
+impl<'a> ConnectConfig<'a> {
+    /// Connect on the coded phy, using `conn_profile`.
+    ///
+    /// `conn_profile` must have an event length long enough for the coded phy, see
+    /// [`ConnProfile::long_range`](crate::ConnProfile::long_range). The supervision timeout is
+    /// raised to 6s to cope with the lower data rate.
+    pub fn long_range(conn_profile: ConnProfileId) -> Self {
+        let mut config = Self {
+            scan_config: ScanConfig::long_range(),
+            conn_profile,
+            ..Default::default()
+        };
+        config.conn_params.conn_sup_timeout = 600;
+        config
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ScanError {
     Timeout,
+    /// The coded phy was requested without extended scanning, or together with the 1Mbps phy and
+    /// a scan interval shorter than twice the scan window.
+    InvalidPhy,
     Raw(RawError),
 }
 
@@ -166,28 +343,36 @@ impl From<RawError> for ScanError {
 
 pub(crate) static SCAN_PORTAL: Portal<*const raw::ble_evt_t> = Portal::new();
 
+// Buffer to store received advertisement data.
+const BUF_LEN: usize = 256;
+
+// Both of these are intentionally static because Softdevice will,
+// sometimes, write to the buffer after scan_stop() has been
+// called, somewhere around evt_get().
+//
+// This can result in UB as a use-after-free, given the buffer
+// has been dropped and the scanning has been stopped.
+static mut BUF: [u8; BUF_LEN] = [0u8; BUF_LEN];
+static mut BUF_DATA: raw::ble_data_t = raw::ble_data_t {
+    p_data: (&raw mut BUF).cast(),
+    len: BUF_LEN as u16,
+};
+
+/// Buffer for advertising data, to pass to `sd_ble_gap_scan_start`. Only one scan can run at a time.
+pub(crate) fn scan_buf() -> *const raw::ble_data_t {
+    ptr::addr_of!(BUF_DATA)
+}
+
 pub async fn scan<'a, F, R>(_sd: &Softdevice, config: &ScanConfig<'a>, mut f: F) -> Result<R, ScanError>
 where
     F: for<'b> FnMut(&'b raw::ble_gap_evt_adv_report_t) -> Option<R>,
 {
+    if !config.phys_valid() {
+        return Err(ScanError::InvalidPhy);
+    }
     let scan_params = config.to_raw()?;
 
-    // Buffer to store received advertisement data.
-    const BUF_LEN: usize = 256;
-
-    // Both of these are intentionally static because Softdevice will,
-    // sometimes, write to the buffer after scan_stop() has been
-    // called, somewhere around evt_get().
-    //
-    // This can result in UB as a use-after-free, given the buffer
-    // has been dropped and the scanning has been stopped.
-    static mut BUF: [u8; BUF_LEN] = [0u8; BUF_LEN];
-    static mut BUF_DATA: raw::ble_data_t = raw::ble_data_t {
-        p_data: unsafe { (&mut *(&raw mut BUF)).as_mut_ptr() },
-        len: BUF_LEN as u16,
-    };
-
-    let ret = unsafe { raw::sd_ble_gap_scan_start(&scan_params, ptr::addr_of!(BUF_DATA)) };
+    let ret = unsafe { raw::sd_ble_gap_scan_start(&scan_params, scan_buf()) };
     match RawError::convert(ret) {
         Ok(()) => {}
         Err(err) => {
@@ -218,7 +403,7 @@ where
                     }
 
                     // Resume scan
-                    let ret = raw::sd_ble_gap_scan_start(ptr::null(), ptr::addr_of!(BUF_DATA));
+                    let ret = raw::sd_ble_gap_scan_start(ptr::null(), scan_buf());
                     match RawError::convert(ret) {
                         Ok(()) => {}
 
@@ -240,6 +425,129 @@ where
     Ok(res)
 }
 
+/// Maximum length of advertising data in a single advertising report.
+pub const ADV_DATA_MAX_LEN: usize = 255;
+
+// The advertising data of a raw report. `p_data` may be null for reports without data, otherwise
+// it must point to `len` readable bytes, as it does in reports from softdevice events.
+pub(crate) unsafe fn report_data(report: &raw::ble_gap_evt_adv_report_t) -> &[u8] {
+    if report.data.p_data.is_null() || report.data.len == 0 {
+        return &[];
+    }
+    core::slice::from_raw_parts(report.data.p_data, report.data.len as usize)
+}
+
+/// Owned, parsed copy of an advertising report received while scanning.
+#[derive(Debug, Clone)]
+pub struct AdvertisementReport {
+    address: Address,
+    rssi: i8,
+    tx_power: i8,
+    primary_phy: u8,
+    secondary_phy: u8,
+    set_id: u8,
+    connectable: bool,
+    scannable: bool,
+    directed: bool,
+    scan_response: bool,
+    extended: bool,
+    status: u8,
+    data: heapless::Vec<u8, ADV_DATA_MAX_LEN>,
+}
+
+impl AdvertisementReport {
+    /// Copy a raw advertising report. The data is truncated to [`ADV_DATA_MAX_LEN`] bytes.
+    ///
+    /// # Safety
+    ///
+    /// `report.data.p_data` must be null or point to `report.data.len` readable bytes. This holds
+    /// for the reports passed to the [`scan`] callback.
+    pub unsafe fn from_raw(report: &raw::ble_gap_evt_adv_report_t) -> Self {
+        let data = report_data(report);
+        let data = &data[..data.len().min(ADV_DATA_MAX_LEN)];
+
+        Self {
+            address: Address::from_raw(report.peer_addr),
+            rssi: report.rssi,
+            tx_power: report.tx_power,
+            primary_phy: report.primary_phy,
+            secondary_phy: report.secondary_phy,
+            set_id: report.set_id,
+            connectable: report.type_.connectable() != 0,
+            scannable: report.type_.scannable() != 0,
+            directed: report.type_.directed() != 0,
+            scan_response: report.type_.scan_response() != 0,
+            extended: report.type_.extended_pdu() != 0,
+            status: report.type_.status() as u8,
+            data: unwrap!(heapless::Vec::from_slice(data)),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Received signal strength in dBm.
+    pub fn rssi(&self) -> i8 {
+        self.rssi
+    }
+
+    /// TX power advertised in the extended advertising header, in dBm.
+    pub fn tx_power(&self) -> Option<i8> {
+        (self.tx_power as u32 != raw::BLE_GAP_POWER_LEVEL_INVALID).then_some(self.tx_power)
+    }
+
+    pub fn primary_phy(&self) -> Phy {
+        Phy::from_raw(self.primary_phy)
+    }
+
+    /// PHY of the secondary advertising channel, for extended advertising.
+    pub fn secondary_phy(&self) -> Option<Phy> {
+        (self.secondary_phy as u32 != raw::BLE_GAP_PHY_NOT_SET).then(|| Phy::from_raw(self.secondary_phy))
+    }
+
+    /// Advertising set id, for extended advertising.
+    pub fn set_id(&self) -> Option<u8> {
+        (self.set_id as u32 != raw::BLE_GAP_ADV_REPORT_SET_ID_NOT_AVAILABLE).then_some(self.set_id)
+    }
+
+    pub fn is_connectable(&self) -> bool {
+        self.connectable
+    }
+
+    pub fn is_scannable(&self) -> bool {
+        self.scannable
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    pub fn is_scan_response(&self) -> bool {
+        self.scan_response
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    /// Whether `data` holds the full advertising data. Extended advertising data may be
+    /// delivered in several reports, or be truncated.
+    pub fn is_data_complete(&self) -> bool {
+        self.status as u32 == raw::BLE_GAP_ADV_DATA_STATUS_COMPLETE
+    }
+
+    /// Raw advertising (or scan response) data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Iterate over the AD structures in the advertising data.
+    pub fn ad_structures(&self) -> AdStructures<'_> {
+        AdStructures::new(&self.data)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct ScanConfig<'a> {
     /// Whitelist of addresses to scan. If None, all advertisements
@@ -293,7 +601,43 @@ impl<'a> Default for ScanConfig<'a> {
 }
 
 impl<'a> ScanConfig<'a> {
-    fn to_raw(&self) -> Result<raw::ble_gap_scan_params_t, RawError> {
+    /// Extended scanning on the coded phy only, for long range.
+    #[cfg(feature = "s140")]
+    pub fn long_range() -> Self {
+        Self {
+            extended: true,
+            phys: PhySet::Coded,
+            ..Default::default()
+        }
+    }
+
+    // Phy of connections made with this config. A connection is made on the phy the advertisement was
+    // received on, which the softdevice doesn't report, so it's only known when scanning on a single phy.
+    fn conn_phy(&self) -> Phy {
+        #[cfg(feature = "s140")]
+        if self.phys == PhySet::Coded {
+            return Phy::Coded;
+        }
+        Phy::M1
+    }
+
+    pub(crate) fn phys_valid(&self) -> bool {
+        #[cfg(feature = "s140")]
+        if self.phys.has_coded() {
+            if !self.extended {
+                warn!("scanning on the coded phy requires extended scanning");
+                return false;
+            }
+            let has_m1 = self.phys as u8 & raw::BLE_GAP_PHY_1MBPS as u8 != 0;
+            if has_m1 && self.interval < self.window * 2 {
+                warn!("scanning on 1Mbps and coded phys requires an interval of at least twice the window");
+                return false;
+            }
+        }
+        true
+    }
+
+    pub(crate) fn to_raw(self) -> Result<raw::ble_gap_scan_params_t, RawError> {
         let mut scan_params: raw::ble_gap_scan_params_t = unsafe { mem::zeroed() };
         if self.extended {
             scan_params.set_extended(1);
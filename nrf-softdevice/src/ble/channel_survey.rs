@@ -0,0 +1,192 @@
+//! QoS channel survey.
+//!
+//! The softdevice can measure the energy on all BLE channels when the radio is otherwise idle.
+//! This can be used to find channels that are busy with other 2.4 GHz traffic, such as Wi-Fi,
+//! and exclude them from the channel map with [`avoid_busy_channels`].
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use futures::future::poll_fn;
+use futures::Stream;
+
+#[cfg(feature = "ble-central")]
+use crate::ble::{set_channel_map, ChannelMap};
+use crate::{raw, RawError, Softdevice};
+
+/// Number of channels measured in a survey, including the advertising channels (37-39).
+pub const CHANNEL_COUNT: usize = raw::BLE_GAP_CHANNEL_COUNT as usize;
+
+/// Energy measured on each channel in one survey report.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelEnergy([i8; CHANNEL_COUNT]);
+
+impl ChannelEnergy {
+    /// Energy measured on `channel` in dBm, or `None` if it was not measured.
+    pub fn get(&self, channel: u8) -> Option<i8> {
+        let energy = *self.0.get(channel as usize)?;
+        (energy as u32 != raw::BLE_GAP_POWER_LEVEL_INVALID).then_some(energy)
+    }
+
+    pub fn as_raw(&self) -> &[i8; CHANNEL_COUNT] {
+        &self.0
+    }
+}
+
+// Holds the latest report not received yet.
+static REPORTS: Channel<CriticalSectionRawMutex, ChannelEnergy, 1> = Channel::new();
+
+pub(crate) fn on_report(report: &raw::ble_gap_evt_qos_channel_survey_report_t) {
+    if REPORTS.is_full() {
+        let _ = REPORTS.try_receive();
+    }
+    let _ = REPORTS.try_send(ChannelEnergy(report.channel_energy));
+}
+
+/// A running channel survey, see [`start`]. The survey is stopped on drop.
+///
+/// Only the latest report is kept, older ones are dropped if not received in time.
+pub struct ChannelSurvey {
+    _private: (),
+}
+
+/// Start a channel survey, reporting the measured energy every `interval_us` microseconds.
+///
+/// `interval_us` must be in `7_500..=4_000_000`, or 0 to measure continuously and report
+/// as often as possible. Only one survey can run at a time.
+pub fn start(sd: &Softdevice, interval_us: u32) -> Result<ChannelSurvey, RawError> {
+    let _ = sd;
+    REPORTS.clear();
+    let ret = unsafe { raw::sd_ble_gap_qos_channel_survey_start(interval_us) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_qos_channel_survey_start err {:?}", err);
+        err
+    })?;
+    Ok(ChannelSurvey { _private: () })
+}
+
+impl ChannelSurvey {
+    /// Wait for the next survey report.
+    pub async fn next(&mut self) -> ChannelEnergy {
+        poll_fn(|cx| REPORTS.poll_receive(cx)).await
+    }
+}
+
+impl Stream for ChannelSurvey {
+    type Item = ChannelEnergy;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        REPORTS.poll_receive(cx).map(Some)
+    }
+}
+
+impl Drop for ChannelSurvey {
+    fn drop(&mut self) {
+        let ret = unsafe { raw::sd_ble_gap_qos_channel_survey_stop() };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_qos_channel_survey_stop err {:?}", err);
+        }
+    }
+}
+
+#[cfg(feature = "ble-central")]
+#[derive(Clone, Copy)]
+pub struct AvoidanceConfig {
+    /// Survey report interval, see [`start`].
+    pub survey_interval_us: u32,
+    /// Channels with an average energy above this, in dBm, are excluded from the channel map.
+    pub busy_threshold_dbm: i8,
+    /// Minimum number of channels to keep enabled, the quietest busy ones are kept if needed.
+    /// The softdevice requires at least 2.
+    pub min_channels: u8,
+    /// Number of survey reports averaged between channel map updates.
+    pub reports_per_update: u8,
+}
+
+#[cfg(feature = "ble-central")]
+impl Default for AvoidanceConfig {
+    fn default() -> Self {
+        Self {
+            survey_interval_us: 100_000,
+            busy_threshold_dbm: -75,
+            min_channels: 20,
+            reports_per_update: 50,
+        }
+    }
+}
+
+/// Survey the channels and keep busy ones out of the channel map.
+///
+/// The channel map is set with [`set_channel_map`], so it applies to all central connections.
+/// This runs until an error occurs.
+#[cfg(feature = "ble-central")]
+pub async fn avoid_busy_channels(sd: &Softdevice, config: &AvoidanceConfig) -> RawError {
+    let mut survey = match start(sd, config.survey_interval_us) {
+        Ok(survey) => survey,
+        Err(err) => return err,
+    };
+
+    // Moving average of the energy on each data channel, in dBm.
+    let mut average: [Option<i16>; ChannelMap::CHANNEL_COUNT as usize] = [None; ChannelMap::CHANNEL_COUNT as usize];
+    let mut current = ChannelMap::ALL;
+    let mut reports = 0;
+
+    loop {
+        let energy = survey.next().await;
+        for (channel, avg) in average.iter_mut().enumerate() {
+            if let Some(e) = energy.get(channel as u8) {
+                *avg = Some(avg.map_or(e as i16, |avg| (avg * 7 + e as i16) / 8));
+            }
+        }
+
+        reports += 1;
+        if reports < config.reports_per_update {
+            continue;
+        }
+        reports = 0;
+
+        let map = quiet_channels(&average, config.busy_threshold_dbm, config.min_channels.max(2));
+        if map == current {
+            continue;
+        }
+
+        debug!("channel survey: {:?} quiet channels, updating channel map", map.count());
+        match set_channel_map(sd, &map) {
+            Ok(()) => current = map,
+            // Set too soon after the previous one, retry on next update.
+            Err(RawError::Busy) => {}
+            Err(err) => return err,
+        }
+    }
+}
+
+// Channels with an average energy at most `threshold`, plus the quietest remaining ones up to `min_channels`.
+// Channels that were never measured count as quiet.
+#[cfg(feature = "ble-central")]
+fn quiet_channels(
+    average: &[Option<i16>; ChannelMap::CHANNEL_COUNT as usize],
+    threshold: i8,
+    min_channels: u8,
+) -> ChannelMap {
+    let mut map = ChannelMap::NONE;
+    for (channel, avg) in average.iter().enumerate() {
+        if avg.map_or(true, |avg| avg <= threshold as i16) {
+            map.set_enabled(channel as u8, true);
+        }
+    }
+
+    while map.count() < min_channels {
+        let quietest = (0..ChannelMap::CHANNEL_COUNT)
+            .filter(|&channel| !map.is_enabled(channel))
+            .min_by_key(|&channel| average[channel as usize]);
+        match quietest {
+            Some(channel) => map.set_enabled(channel, true),
+            None => break,
+        }
+    }
+
+    map
+}
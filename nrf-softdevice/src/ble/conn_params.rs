@@ -0,0 +1,178 @@
+//! Connection parameter negotiation.
+//!
+//! This is the equivalent of the nRF5 SDK's `ble_conn_params` module: once a connection is up,
+//! a [`ConnParamsManager`] checks the connection parameters picked by the central and, if they
+//! are outside the configured range, requests the preferred ones, retrying a few times before
+//! giving up.
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+use raw::ble_gap_conn_params_t;
+
+use crate::ble::{Connection, DisconnectedError, HciStatus, SetConnParamsError, CONNS_MAX};
+use crate::util::{get_union_field, OnDrop, Portal};
+use crate::{raw, RawError};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnParamsError {
+    Disconnected,
+    /// [`ConnParamsManager::run`] is already running on this connection.
+    Busy,
+    /// The connection parameters were still not acceptable after `max_update_count` update requests.
+    Failed,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for ConnParamsError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<SetConnParamsError> for ConnParamsError {
+    fn from(err: SetConnParamsError) -> Self {
+        match err {
+            SetConnParamsError::Disconnected => Self::Disconnected,
+            SetConnParamsError::Raw(err) => Self::Raw(err),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Preferred connection parameters.
+    ///
+    /// The connection parameters are acceptable if the connection interval lies within
+    /// `min_conn_interval..=max_conn_interval`, the slave latency is at most `slave_latency` and the
+    /// supervision timeout is at most `conn_sup_timeout`.
+    /// These are also the parameters sent in update requests.
+    pub preferred: ble_gap_conn_params_t,
+    /// Time to wait after the manager is started before sending the first update request.
+    ///
+    /// This gives the central the chance to do service discovery and pick better parameters by itself.
+    pub first_update_delay: Duration,
+    /// Time to wait for an update request to take effect before sending the next one.
+    pub next_update_delay: Duration,
+    /// Number of update requests to send before giving up.
+    pub max_update_count: u8,
+    /// Disconnect with [`HciStatus::CONN_INTERVAL_UNACCEPTABLE`] when giving up.
+    pub disconnect_on_fail: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            preferred: ble_gap_conn_params_t {
+                min_conn_interval: 40,
+                max_conn_interval: 200,
+                slave_latency: 0,
+                conn_sup_timeout: 400, // 4s
+            },
+            first_update_delay: Duration::from_secs(5),
+            next_update_delay: Duration::from_secs(30),
+            max_update_count: 3,
+            disconnect_on_fail: false,
+        }
+    }
+}
+
+/// Per-connection connection parameter negotiation policy.
+pub struct ConnParamsManager {
+    config: Config,
+}
+
+impl ConnParamsManager {
+    pub const fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Negotiate connection parameters on `conn`.
+    ///
+    /// Returns the accepted connection parameters once the active ones are within the preferred range.
+    ///
+    /// For central connections, this additionally makes the connection reject update requests from
+    /// the peripheral that fall outside the preferred range. This keeps applying until disconnection,
+    /// even after this future has completed or been dropped.
+    ///
+    /// Returns [`ConnParamsError::Busy`] if another `run` is in progress on the same connection.
+    pub async fn run(&self, conn: &Connection) -> Result<ble_gap_conn_params_t, ConnParamsError> {
+        let preferred = self.config.preferred;
+        let conn_handle = conn.with_state(|state| {
+            let conn_handle = state.check_connected()?;
+            if state.conn_params_running {
+                return Err(ConnParamsError::Busy);
+            }
+            state.conn_params_running = true;
+            state.conn_params_bounds = Some(preferred);
+            Ok(conn_handle)
+        })?;
+        let _running = OnDrop::new(|| conn.with_state(|state| state.conn_params_running = false));
+
+        let mut current = conn.conn_params();
+        let mut update_count = 0;
+        let mut deadline = Instant::now() + self.config.first_update_delay;
+
+        loop {
+            if within_bounds(&preferred, &current) {
+                debug!("conn_params: accepted");
+                return Ok(current);
+            }
+
+            if let Either::Second(res) = select(Timer::at(deadline), wait_update(conn_handle)).await {
+                current = res?;
+                continue;
+            }
+
+            if update_count >= self.config.max_update_count {
+                warn!("conn_params: giving up after {:?} update requests", update_count);
+                if self.config.disconnect_on_fail {
+                    let _ = conn.disconnect_with_reason(HciStatus::CONN_INTERVAL_UNACCEPTABLE);
+                }
+                return Err(ConnParamsError::Failed);
+            }
+
+            match conn.set_conn_params(preferred) {
+                Ok(()) => update_count += 1,
+                // Another procedure is in progress, try again after the next delay.
+                Err(SetConnParamsError::Raw(RawError::Busy)) => {}
+                Err(err) => return Err(err.into()),
+            }
+            deadline = Instant::now() + self.config.next_update_delay;
+        }
+    }
+}
+
+async fn wait_update(conn_handle: u16) -> Result<ble_gap_conn_params_t, DisconnectedError> {
+    portal(conn_handle)
+        .wait_once(|ble_evt| unsafe {
+            match (*ble_evt).header.evt_id as u32 {
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(DisconnectedError),
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONN_PARAM_UPDATE => {
+                    let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                    Ok(gap_evt.params.conn_param_update.conn_params)
+                }
+                e => panic!("unexpected event {}", e),
+            }
+        })
+        .await
+}
+
+/// Whether `params` (active parameters or a peripheral's update request) lie within `bounds`.
+pub(crate) fn within_bounds(bounds: &ble_gap_conn_params_t, params: &ble_gap_conn_params_t) -> bool {
+    params.min_conn_interval >= bounds.min_conn_interval
+        && params.max_conn_interval <= bounds.max_conn_interval
+        && params.slave_latency <= bounds.slave_latency
+        && params.conn_sup_timeout <= bounds.conn_sup_timeout
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const PORTAL_NEW: Portal<*const raw::ble_evt_t> = Portal::new();
+static PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+pub(crate) fn portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &PORTALS[conn_handle as usize]
+}
@@ -1,19 +1,45 @@
+use core::any::TypeId;
+#[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+use core::cell::RefCell;
 use core::cell::{Cell, UnsafeCell};
 use core::iter::FusedIterator;
-
+use core::mem::{align_of, size_of, MaybeUninit};
+#[cfg(feature = "ble-conn-events")]
+use core::pin::Pin;
+#[cfg(feature = "ble-conn-events")]
+use core::task::Context;
+use core::task::Poll;
+
+#[cfg(feature = "ble-conn-events")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+use embassy_sync::blocking_mutex::Mutex;
+#[cfg(feature = "ble-conn-events")]
+use embassy_sync::channel::Channel;
+#[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+use embassy_sync::waitqueue::WakerRegistration;
+#[cfg(feature = "ble-conn-events")]
+use futures::future::poll_fn;
+#[cfg(feature = "ble-conn-events")]
+use futures::Stream;
 use raw::ble_gap_conn_params_t;
 
-use super::{HciStatus, PhySet};
+use super::{ChannelMap, HciStatus, Phy, PhySet, TxPower};
 #[cfg(feature = "ble-central")]
 use crate::ble::gap::default_security_params;
 #[cfg(feature = "ble-sec")]
 use crate::ble::security::SecurityHandler;
 use crate::ble::types::{Address, AddressType, Role, SecurityMode};
-use crate::util::get_union_field;
-use crate::{raw, RawError};
+use crate::util::{get_union_field, OnDrop, Portal, Waiters};
+use crate::{raw, ConnProfileId, RawError};
 
 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
 const BLE_GAP_DATA_LENGTH_DEFAULT: u8 = 27; //  The stack's default data length. <27-251>
+#[cfg(all(
+    any(feature = "s113", feature = "s132", feature = "s140"),
+    feature = "ble-data-length-wait"
+))]
+const BLE_GAP_DATA_LENGTH_DEFAULT_TIME_US: u16 = 328; // Air time of a default length packet on the 1M PHY.
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -23,6 +49,20 @@ pub(crate) struct OutOfConnsError;
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DisconnectedError;
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SetUserDataError {
+    Disconnected,
+    /// The data is in use by [`Connection::with_user_data`].
+    Borrowed,
+}
+
+impl From<DisconnectedError> for SetUserDataError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) enum ConnHandleState {
@@ -98,12 +138,55 @@ impl From<RawError> for IgnoreSlaveLatencyError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SetTxPowerError {
+    Disconnected,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for SetTxPowerError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<RawError> for SetTxPowerError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelMapError {
+    Disconnected,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for ChannelMapError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<RawError> for ChannelMapError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
 #[derive(Debug, Clone, Copy)]
 pub enum DataLengthUpdateError {
     Disconnected,
+    /// Another task is already waiting for a data length update on this connection.
+    Busy,
     NotSupported(raw::ble_gap_data_length_limitation_t),
     Resources(raw::ble_gap_data_length_limitation_t),
+    /// The peer didn't answer with new parameters within a supervision timeout, it rejected or
+    /// ignored the request.
+    Rejected,
     Raw(RawError),
 }
 
@@ -114,8 +197,16 @@ impl From<DisconnectedError> for DataLengthUpdateError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PhyUpdateError {
     Disconnected,
+    /// Another task is already waiting for a PHY update on this connection.
+    Busy,
+    /// The PHY update procedure collided with another procedure, it can be retried.
+    Collision,
+    /// The PHY update procedure failed, for example because the peer rejected it.
+    Rejected(HciStatus),
     Raw(RawError),
 }
 
@@ -177,15 +268,79 @@ impl From<RawError> for EncryptError {
     }
 }
 
-// Highest ever the softdevice can support.
+// Maximum number of simultaneous connections, selected with the `conns-max-*` features.
+// Defaults to the highest ever the softdevice can support.
+#[cfg(feature = "conns-max-16")]
+pub(crate) const CONNS_MAX: usize = 16;
+#[cfg(all(feature = "conns-max-8", not(feature = "conns-max-16")))]
+pub(crate) const CONNS_MAX: usize = 8;
+#[cfg(all(feature = "conns-max-4", not(any(feature = "conns-max-8", feature = "conns-max-16"))))]
+pub(crate) const CONNS_MAX: usize = 4;
+#[cfg(all(
+    feature = "conns-max-2",
+    not(any(feature = "conns-max-4", feature = "conns-max-8", feature = "conns-max-16"))
+))]
+pub(crate) const CONNS_MAX: usize = 2;
+#[cfg(all(
+    feature = "conns-max-1",
+    not(any(
+        feature = "conns-max-2",
+        feature = "conns-max-4",
+        feature = "conns-max-8",
+        feature = "conns-max-16"
+    ))
+))]
+pub(crate) const CONNS_MAX: usize = 1;
+#[cfg(not(any(
+    feature = "conns-max-1",
+    feature = "conns-max-2",
+    feature = "conns-max-4",
+    feature = "conns-max-8",
+    feature = "conns-max-16"
+)))]
 pub(crate) const CONNS_MAX: usize = 20;
 
+/// An event on a connection, see [`Connection::events`].
+#[cfg(feature = "ble-conn-events")]
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectionEvent {
+    /// The connection parameters changed.
+    ParamsUpdated(ble_gap_conn_params_t),
+    /// A PHY update procedure completed successfully.
+    PhyUpdated { tx_phy: Phy, rx_phy: Phy },
+    /// A data length update procedure completed, with the effective parameters.
+    #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+    DataLengthUpdated(raw::ble_gap_data_length_params_t),
+    /// The security level of the connection changed.
+    SecurityChanged(SecurityMode),
+    /// A new RSSI measurement, see [`Connection::start_rssi`].
+    ///
+    /// Measurements are not queued like the other events, only the latest one is reported.
+    #[cfg(feature = "ble-rssi")]
+    RssiChanged(i8),
+    /// The connection was disconnected. This is always the last event.
+    Disconnected(HciStatus),
+}
+
+// Events not received yet, older ones are dropped when full.
+#[cfg(feature = "ble-conn-events")]
+const EVENTS_QUEUE_LEN: usize = 4;
+
+/// Maximum number of tasks awaiting [`Connection::disconnected`] at once on the same connection.
+pub const DISCONNECTED_WAITERS: usize = 4;
+
+/// Maximum number of tasks awaiting [`Connection::wait_rssi_below`] or
+/// [`Connection::wait_rssi_above`] at once on the same connection.
+#[cfg(feature = "ble-rssi")]
+pub const RSSI_WAITERS: usize = 4;
+
 #[cfg(feature = "ble-sec")]
 #[derive(Clone, Copy)]
 pub(crate) struct EncryptionState {
     pub handler: Option<&'static dyn SecurityHandler>,
 
     pub own_enc_key: raw::ble_gap_enc_key_t,
+    pub own_id: raw::ble_gap_id_key_t,
     pub peer_enc_key: raw::ble_gap_enc_key_t,
     pub peer_id: raw::ble_gap_id_key_t,
 }
@@ -212,10 +367,91 @@ const NEW_GAP_ID_KEY: raw::ble_gap_id_key_t = raw::ble_gap_id_key_t {
 const NEW_ENCRYPTION_STATE: EncryptionState = EncryptionState {
     handler: None,
     own_enc_key: NEW_GAP_ENC_KEY,
+    own_id: NEW_GAP_ID_KEY,
     peer_enc_key: NEW_GAP_ENC_KEY,
     peer_id: NEW_GAP_ID_KEY,
 };
 
+/// Maximum size in bytes of the application data attached to a connection, see [`Connection::set_user_data`].
+pub const USER_DATA_SIZE: usize = 32;
+
+#[repr(C, align(8))]
+struct UserDataBuf([MaybeUninit<u8>; USER_DATA_SIZE]);
+
+// Type-erased storage for the application data attached to a connection.
+//
+// The data is moved out of the connection state before being dropped, as its `Drop` may use the
+// connection again.
+pub(crate) struct UserData {
+    type_id: Option<TypeId>,
+    drop: Option<unsafe fn(*mut u8)>,
+    // Set while the data is lent out by `Connection::with_user_data`.
+    borrowed: bool,
+    buf: UserDataBuf,
+}
+
+unsafe fn drop_user_data<T>(ptr: *mut u8) {
+    ptr.cast::<T>().drop_in_place()
+}
+
+impl UserData {
+    const fn new() -> Self {
+        Self {
+            type_id: None,
+            drop: None,
+            borrowed: false,
+            buf: UserDataBuf([MaybeUninit::uninit(); USER_DATA_SIZE]),
+        }
+    }
+
+    fn with_value<T: 'static>(value: T) -> Self {
+        assert!(
+            size_of::<T>() <= USER_DATA_SIZE && align_of::<T>() <= align_of::<UserDataBuf>(),
+            "connection user data too big, max size is USER_DATA_SIZE"
+        );
+        let mut this = Self::new();
+        unsafe { this.buf.0.as_mut_ptr().cast::<T>().write(value) };
+        this.type_id = Some(TypeId::of::<T>());
+        this.drop = Some(drop_user_data::<T>);
+        this
+    }
+
+    fn is<T: 'static>(&self) -> bool {
+        !self.borrowed && self.type_id == Some(TypeId::of::<T>())
+    }
+
+    // Lend out the data, until `borrowed` is cleared.
+    fn borrow_mut<T: 'static>(&mut self) -> Option<*mut T> {
+        if !self.is::<T>() {
+            return None;
+        }
+        self.borrowed = true;
+        Some(self.buf.0.as_mut_ptr().cast::<T>())
+    }
+
+    fn take<T: 'static>(&mut self) -> Option<T> {
+        if !self.is::<T>() {
+            return None;
+        }
+        self.type_id = None;
+        self.drop = None;
+        Some(unsafe { self.buf.0.as_mut_ptr().cast::<T>().read() })
+    }
+
+    // Move out the data, unless it's borrowed.
+    pub(crate) fn take_all(&mut self) -> Option<UserData> {
+        (!self.borrowed).then(|| core::mem::replace(self, Self::new()))
+    }
+}
+
+impl Drop for UserData {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop.take() {
+            unsafe { drop(self.buf.0.as_mut_ptr().cast()) };
+        }
+    }
+}
+
 // We could make the public Connection type simply hold the softdevice's conn_handle.
 // However, that would allow for bugs like:
 // - Connection is established with conn_handle=5
@@ -234,6 +470,9 @@ pub(crate) struct ConnectionState {
     // However, disconnection is not complete until the event GAP_DISCONNECTED.
     // so there's a small gap of time where the ConnectionState is not "free" even if refcount=0.
     pub refcount: u8,
+    // Bumped each time the index is allocated, so `WeakConnection`s notice it being reused.
+    #[cfg(feature = "ble-peripheral-manager")]
+    pub generation: u8,
     pub conn_handle: ConnHandleState,
 
     pub disconnecting: bool,
@@ -242,17 +481,54 @@ pub(crate) struct ConnectionState {
     pub security_mode: SecurityMode,
 
     pub conn_params: ble_gap_conn_params_t,
+    // Set by `ConnParamsManager::run`, peripheral update requests outside these are rejected.
+    #[cfg(feature = "ble-conn-params")]
+    pub conn_params_bounds: Option<ble_gap_conn_params_t>,
+    // Set while `ConnParamsManager::run` is in progress, it can't run twice on the same connection.
+    #[cfg(feature = "ble-conn-params")]
+    pub conn_params_running: bool,
 
     #[cfg(feature = "ble-rssi")]
     pub rssi: Option<i8>,
+    // Last RSSI reported by the softdevice, `rssi` is a moving average of these that lags behind
+    // step changes reported once with a change threshold.
+    #[cfg(feature = "ble-rssi")]
+    pub rssi_reported: Option<i8>,
+    // Latest RSSI measurement not received by `ConnectionEvents` yet. It's kept out of the events
+    // queue so frequent measurements don't evict the other events.
+    #[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+    pub rssi_event: Option<i8>,
 
     #[cfg(feature = "ble-gatt")]
     pub att_mtu: u16, // Effective ATT_MTU size (in bytes).
     #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
     pub data_length_effective: u8, // Effective data length (in bytes).
+    #[cfg(all(
+        any(feature = "s113", feature = "s132", feature = "s140"),
+        feature = "ble-data-length-wait"
+    ))]
+    pub data_length_params: raw::ble_gap_data_length_params_t, // Effective data length parameters.
+    pub tx_phy: u8, // Active TX PHY (BLE_GAP_PHY_*).
+    pub rx_phy: u8, // Active RX PHY (BLE_GAP_PHY_*).
+    pub tx_power: TxPower,
+    pub conn_profile: ConnProfileId,
+
+    // Set while a task waits for the result of the procedure, only one can wait at a time.
+    pub phy_update_waiting: bool,
+    #[cfg(all(
+        any(feature = "s113", feature = "s132", feature = "s140"),
+        feature = "ble-data-length-wait"
+    ))]
+    pub data_length_update_waiting: bool,
 
     #[cfg(feature = "ble-sec")]
     pub security: EncryptionState,
+    // Identity address of the peer once the link is encrypted with a bond, either created during
+    // this connection or restored from the security handler.
+    #[cfg(feature = "ble-sec")]
+    pub bonded_peer: Option<Address>,
+
+    pub user_data: UserData,
 }
 
 impl ConnectionState {
@@ -261,6 +537,8 @@ impl ConnectionState {
         // can go into .bss instead of .data, which saves flash space.
         Self {
             refcount: 0,
+            #[cfg(feature = "ble-peripheral-manager")]
+            generation: 0,
             conn_handle: ConnHandleState::Disconnected(HciStatus::SUCCESS),
             #[cfg(feature = "ble-central")]
             role: Role::Central,
@@ -275,14 +553,45 @@ impl ConnectionState {
                 min_conn_interval: 0,
                 slave_latency: 0,
             },
+            #[cfg(feature = "ble-conn-params")]
+            conn_params_bounds: None,
+            #[cfg(feature = "ble-conn-params")]
+            conn_params_running: false,
             #[cfg(feature = "ble-rssi")]
             rssi: None,
+            #[cfg(feature = "ble-rssi")]
+            rssi_reported: None,
+            #[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+            rssi_event: None,
             #[cfg(feature = "ble-gatt")]
             att_mtu: 0,
             #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
             data_length_effective: 0,
+            #[cfg(all(
+                any(feature = "s113", feature = "s132", feature = "s140"),
+                feature = "ble-data-length-wait"
+            ))]
+            data_length_params: raw::ble_gap_data_length_params_t {
+                max_tx_octets: 0,
+                max_rx_octets: 0,
+                max_tx_time_us: 0,
+                max_rx_time_us: 0,
+            },
+            tx_phy: 0,
+            rx_phy: 0,
+            tx_power: TxPower::ZerodBm,
+            conn_profile: ConnProfileId::DEFAULT,
+            phy_update_waiting: false,
+            #[cfg(all(
+                any(feature = "s113", feature = "s132", feature = "s140"),
+                feature = "ble-data-length-wait"
+            ))]
+            data_length_update_waiting: false,
             #[cfg(feature = "ble-sec")]
             security: NEW_ENCRYPTION_STATE,
+            #[cfg(feature = "ble-sec")]
+            bonded_peer: None,
+            user_data: UserData::new(),
         }
     }
     pub(crate) fn check_connected(&mut self) -> Result<u16, DisconnectedError> {
@@ -320,7 +629,7 @@ impl ConnectionState {
         );
 
         let ibh = index_by_handle(conn_handle);
-        let _index = unwrap!(ibh.get(), "bug: conn_handle has no index");
+        let index = unwrap!(ibh.get(), "bug: conn_handle has no index");
 
         #[cfg(all(feature = "ble-gatt-server", feature = "ble-sec"))]
         if let Some(handler) = self.security.handler {
@@ -340,6 +649,11 @@ impl ConnectionState {
             HciStatus::new(gap_evt.params.disconnected.reason)
         };
         self.conn_handle = ConnHandleState::Disconnected(reason);
+        #[cfg(feature = "ble-conn-events")]
+        push_event(index, ConnectionEvent::Disconnected(reason));
+        DISCONNECTED[index as usize].wake();
+        #[cfg(feature = "ble-rssi")]
+        RSSI_CHANGED[index as usize].wake();
 
         // Signal possible in-progess operations that the connection has disconnected.
         #[cfg(feature = "ble-gatt-client")]
@@ -350,8 +664,13 @@ impl ConnectionState {
         crate::ble::gatt_server::portal(conn_handle).call(ble_evt);
         #[cfg(feature = "ble-l2cap")]
         crate::ble::l2cap::portal(conn_handle).call(ble_evt);
+        phy_portal(conn_handle).call(ble_evt);
+        #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+        data_length_portal(conn_handle).call(ble_evt);
+        #[cfg(feature = "ble-conn-params")]
+        crate::ble::conn_params::portal(conn_handle).call(ble_evt);
 
-        trace!("conn {:?}: disconnected", _index);
+        trace!("conn {:?}: disconnected", index);
     }
 
     pub(crate) fn keyset(&mut self) -> raw::ble_gap_sec_keyset_t {
@@ -359,7 +678,8 @@ impl ConnectionState {
         return raw::ble_gap_sec_keyset_t {
             keys_own: raw::ble_gap_sec_keys_t {
                 p_enc_key: &mut self.security.own_enc_key,
-                p_id_key: core::ptr::null_mut(),
+                // The softdevice distributes the device IRK (see `set_privacy`) and identity address here.
+                p_id_key: &mut self.security.own_id,
                 p_sign_key: core::ptr::null_mut(),
                 p_pk: core::ptr::null_mut(),
             },
@@ -425,7 +745,63 @@ impl Clone for Connection {
     }
 }
 
+// A connection handle that doesn't count as a reference, so it doesn't keep the connection up.
+//
+// The index may be reused by an unrelated connection once the connection is down and all
+// `Connection` instances are dropped, which is detected by comparing generations.
+#[cfg(feature = "ble-peripheral-manager")]
+#[derive(Clone, Copy)]
+pub(crate) struct WeakConnection {
+    index: u8,
+    generation: u8,
+}
+
+#[cfg(feature = "ble-peripheral-manager")]
+impl WeakConnection {
+    // Returns `None` if the index has been reused, the connection is then disconnected.
+    pub(crate) fn upgrade(&self) -> Option<Connection> {
+        with_state(self.index, |state| {
+            if state.generation != self.generation {
+                return None;
+            }
+            state.refcount = unwrap!(state.refcount.checked_add(1), "Too many references to same connection");
+            Some(Connection { index: self.index })
+        })
+    }
+
+    // Resolves once the connection is disconnected, it doesn't keep it up while waiting.
+    pub(crate) fn disconnected(&self) -> impl core::future::Future<Output = ()> + Unpin {
+        let Self { index, generation } = *self;
+        DISCONNECTED[index as usize].wait(move || {
+            with_state(index, |state| {
+                if state.generation != generation || state.conn_handle.disconnect_reason().is_some() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+        })
+    }
+}
+
 impl Connection {
+    #[cfg(feature = "ble-peripheral-manager")]
+    pub(crate) fn downgrade(&self) -> WeakConnection {
+        WeakConnection {
+            index: self.index,
+            generation: self.with_state(|state| state.generation),
+        }
+    }
+
+    // Identity address of the peer if the link is bonded.
+    #[cfg(feature = "ble-peripheral-manager")]
+    pub(crate) fn bonded_peer(&self) -> Option<Address> {
+        #[cfg(feature = "ble-sec")]
+        return self.with_state(|state| state.bonded_peer);
+        #[cfg(not(feature = "ble-sec"))]
+        None
+    }
+
     pub fn role(&self) -> Role {
         self.with_state(|state| state.role)
     }
@@ -446,6 +822,19 @@ impl Connection {
         self.with_state(|state| state.conn_handle.disconnect_reason())
     }
 
+    /// Wait until the connection is disconnected, returning the reason.
+    ///
+    /// Returns immediately if it's already disconnected. Up to [`DISCONNECTED_WAITERS`] tasks can
+    /// wait concurrently on the same connection, waiting with more panics.
+    pub async fn disconnected(&self) -> HciStatus {
+        DISCONNECTED[self.index as usize]
+            .wait(|| match self.disconnect_reason() {
+                Some(reason) => Poll::Ready(reason),
+                None => Poll::Pending,
+            })
+            .await
+    }
+
     pub fn handle(&self) -> Option<u16> {
         self.with_state(|state| state.conn_handle.handle())
     }
@@ -469,6 +858,8 @@ impl Connection {
             // Initialize
             *state = ConnectionState {
                 refcount: 1,
+                #[cfg(feature = "ble-peripheral-manager")]
+                generation: state.generation.wrapping_add(1),
                 conn_handle: ConnHandleState::Connected(conn_handle),
                 role,
                 peer_address,
@@ -477,20 +868,57 @@ impl Connection {
                 disconnecting: false,
 
                 conn_params,
+                #[cfg(feature = "ble-conn-params")]
+                conn_params_bounds: None,
+                #[cfg(feature = "ble-conn-params")]
+                conn_params_running: false,
 
                 #[cfg(feature = "ble-rssi")]
                 rssi: None,
+                #[cfg(feature = "ble-rssi")]
+                rssi_reported: None,
+                #[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+                rssi_event: None,
 
                 #[cfg(feature = "ble-gatt")]
                 att_mtu: raw::BLE_GATT_ATT_MTU_DEFAULT as _,
 
                 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
                 data_length_effective: BLE_GAP_DATA_LENGTH_DEFAULT,
+                #[cfg(all(
+                    any(feature = "s113", feature = "s132", feature = "s140"),
+                    feature = "ble-data-length-wait"
+                ))]
+                data_length_params: raw::ble_gap_data_length_params_t {
+                    max_tx_octets: BLE_GAP_DATA_LENGTH_DEFAULT.into(),
+                    max_rx_octets: BLE_GAP_DATA_LENGTH_DEFAULT.into(),
+                    max_tx_time_us: BLE_GAP_DATA_LENGTH_DEFAULT_TIME_US,
+                    max_rx_time_us: BLE_GAP_DATA_LENGTH_DEFAULT_TIME_US,
+                },
+                tx_phy: raw::BLE_GAP_PHY_1MBPS as u8,
+                rx_phy: raw::BLE_GAP_PHY_1MBPS as u8,
+                tx_power: TxPower::ZerodBm,
+                conn_profile: ConnProfileId::DEFAULT,
+
+                phy_update_waiting: false,
+                #[cfg(all(
+                    any(feature = "s113", feature = "s132", feature = "s140"),
+                    feature = "ble-data-length-wait"
+                ))]
+                data_length_update_waiting: false,
 
                 #[cfg(feature = "ble-sec")]
                 security: NEW_ENCRYPTION_STATE,
+                #[cfg(feature = "ble-sec")]
+                bonded_peer: None,
+
+                user_data: UserData::new(),
             };
 
+            // Drop events left over from the previous connection at this index.
+            #[cfg(feature = "ble-conn-events")]
+            EVENTS[index as usize].clear();
+
             // Update index_by_handle
             let ibh = index_by_handle(conn_handle);
             assert!(ibh.get().is_none(), "bug: conn_handle already has index");
@@ -517,8 +945,17 @@ impl Connection {
     /// Start measuring RSSI on this connection.
     #[cfg(feature = "ble-rssi")]
     pub fn start_rssi(&self) {
+        self.start_rssi_with(0, 0)
+    }
+
+    /// Start measuring RSSI on this connection, with change reporting parameters.
+    ///
+    /// A new RSSI value is only reported once it differs from the last reported one by at
+    /// least `threshold_dbm`, and stays so for `skip_count` measurements.
+    #[cfg(feature = "ble-rssi")]
+    pub fn start_rssi_with(&self, threshold_dbm: u8, skip_count: u8) {
         if let Ok(conn_handle) = self.with_state(|state| state.check_connected()) {
-            let ret = unsafe { raw::sd_ble_gap_rssi_start(conn_handle, 0, 0) };
+            let ret = unsafe { raw::sd_ble_gap_rssi_start(conn_handle, threshold_dbm, skip_count) };
             if let Err(err) = RawError::convert(ret) {
                 warn!("sd_ble_gap_rssi_start err {:?}", err);
             }
@@ -534,11 +971,202 @@ impl Connection {
         self.with_state(|state| state.rssi)
     }
 
+    /// Get the index of the channel on which the last RSSI measurement was done.
+    ///
+    /// This will return None if `start_rssi` has not been called yet, or if
+    /// no measurement has been done yet.
+    #[cfg(feature = "ble-rssi")]
+    pub fn rssi_channel_index(&self) -> Option<u8> {
+        let conn_handle = self.with_state(|state| state.check_connected()).ok()?;
+        let mut rssi: i8 = 0;
+        let mut ch_index: u8 = 0;
+        let ret = unsafe { raw::sd_ble_gap_rssi_get(conn_handle, &mut rssi, &mut ch_index) };
+        RawError::convert(ret).ok().map(|()| ch_index)
+    }
+
+    /// Wait until the last RSSI reported for the connection is below `dbm`.
+    ///
+    /// Unlike the average returned by [`Connection::rssi`], this follows a change reported once
+    /// with the threshold of [`Connection::start_rssi_with`] right away.
+    ///
+    /// Returns immediately if it already is. `start_rssi` must have been called. Up to
+    /// [`RSSI_WAITERS`] tasks can wait on the same connection at once, waiting with more panics.
+    #[cfg(feature = "ble-rssi")]
+    pub async fn wait_rssi_below(&self, dbm: i8) -> Result<i8, DisconnectedError> {
+        self.wait_rssi(|rssi| rssi < dbm).await
+    }
+
+    /// Wait until the last RSSI reported for the connection is above `dbm`.
+    ///
+    /// See [`Connection::wait_rssi_below`].
+    ///
+    /// Returns immediately if it already is. `start_rssi` must have been called. Up to
+    /// [`RSSI_WAITERS`] tasks can wait on the same connection at once, waiting with more panics.
+    #[cfg(feature = "ble-rssi")]
+    pub async fn wait_rssi_above(&self, dbm: i8) -> Result<i8, DisconnectedError> {
+        self.wait_rssi(|rssi| rssi > dbm).await
+    }
+
+    #[cfg(feature = "ble-rssi")]
+    pub(crate) async fn wait_rssi(&self, f: impl Fn(i8) -> bool + Unpin) -> Result<i8, DisconnectedError> {
+        RSSI_CHANGED[self.index as usize]
+            .wait(|| {
+                let res = self.with_state(|state| {
+                    state.check_connected()?;
+                    Ok(state.rssi_reported.filter(|&rssi| f(rssi)))
+                });
+                match res {
+                    Err(err) => Poll::Ready(Err(err)),
+                    Ok(Some(rssi)) => Poll::Ready(Ok(rssi)),
+                    Ok(None) => Poll::Pending,
+                }
+            })
+            .await
+    }
+
+    /// Wait for the next RSSI report. May also return early, for example on disconnection.
+    #[cfg(feature = "ble-rssi")]
+    pub(crate) async fn wait_next_rssi(&self) {
+        let mut registered = false;
+        RSSI_CHANGED[self.index as usize]
+            .wait(move || {
+                if registered {
+                    return Poll::Ready(());
+                }
+                registered = true;
+                Poll::Pending
+            })
+            .await
+    }
+
+    /// Set the radio transmit power for this connection.
+    ///
+    /// Connections start at the TX power they were established with, that is the advertising
+    /// [`TxPower`] for peripherals and the scanning one for centrals. With the `ble-rssi` feature,
+    /// `tx_power::run` can adjust it automatically based on RSSI.
+    pub fn set_tx_power(&self, tx_power: TxPower) -> Result<(), SetTxPowerError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let ret = unsafe {
+            raw::sd_ble_gap_tx_power_set(
+                raw::BLE_GAP_TX_POWER_ROLES_BLE_GAP_TX_POWER_ROLE_CONN as _,
+                conn_handle,
+                tx_power as i8,
+            )
+        };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_tx_power_set err {:?}", err);
+            return Err(err.into());
+        }
+
+        self.with_state(|state| state.tx_power = tx_power);
+        Ok(())
+    }
+
+    /// Get the radio transmit power for this connection.
+    pub fn tx_power(&self) -> TxPower {
+        self.with_state(|state| state.tx_power)
+    }
+
+    /// Get the connection profile this connection was established with.
+    pub fn conn_profile(&self) -> ConnProfileId {
+        self.with_state(|state| state.conn_profile)
+    }
+
+    /// Get the data channel map currently in use on this connection.
+    ///
+    /// It can't be set per connection, [`set_channel_map`](crate::ble::set_channel_map) applies to
+    /// all connections in the central role.
+    pub fn channel_map(&self) -> Result<ChannelMap, ChannelMapError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let mut opt = raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                ch_map: raw::ble_gap_opt_ch_map_t {
+                    conn_handle,
+                    ch_map: [0; 5],
+                },
+            },
+        };
+        let ret = unsafe { raw::sd_ble_opt_get(raw::BLE_GAP_OPTS_BLE_GAP_OPT_CH_MAP, &mut opt) };
+        RawError::convert(ret).map_err(|err| {
+            warn!("channel_map sd_ble_opt_get err {:?}", err);
+            err
+        })?;
+        Ok(ChannelMap::from_raw(unsafe { opt.gap_opt.ch_map.ch_map }))
+    }
+
     /// Get the currently active connection params.
     pub fn conn_params(&self) -> ble_gap_conn_params_t {
         with_state(self.index, |s| s.conn_params)
     }
 
+    /// Attach application data to this connection, replacing (and dropping) any previous data.
+    ///
+    /// The data is dropped when the connection is disconnected. `T` must be at most
+    /// [`USER_DATA_SIZE`] bytes big and have an alignment of at most 8.
+    ///
+    /// Returns [`SetUserDataError::Borrowed`] if called from [`with_user_data`](Self::with_user_data)
+    /// on this same connection.
+    pub fn set_user_data<T: 'static>(&self, data: T) -> Result<(), SetUserDataError> {
+        let data = UserData::with_value(data);
+        let res = self.with_state(|state| {
+            if state.check_connected().is_err() {
+                return Err((SetUserDataError::Disconnected, data));
+            }
+            if state.user_data.borrowed {
+                return Err((SetUserDataError::Borrowed, data));
+            }
+            Ok(core::mem::replace(&mut state.user_data, data))
+        });
+
+        // Drop outside of the state borrow.
+        match res {
+            Ok(_old) => Ok(()),
+            Err((err, _data)) => Err(err),
+        }
+    }
+
+    /// Access the application data attached to this connection.
+    ///
+    /// Returns `None` if no data is attached, or if it's not of type `T`. The data can't be accessed
+    /// from `f` itself: nested calls to `with_user_data` return `None`, and so does
+    /// [`take_user_data`](Self::take_user_data).
+    pub fn with_user_data<T: 'static, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let ptr = self.with_state(|state| state.user_data.borrow_mut::<T>())?;
+        let index = self.index;
+        let _borrow = OnDrop::new(move || {
+            let data = with_state(index, |state| {
+                state.user_data.borrowed = false;
+                // Disconnection doesn't drop borrowed data, it's left to us.
+                if state.conn_handle.is_connected() {
+                    None
+                } else {
+                    state.user_data.take_all()
+                }
+            });
+            drop(data);
+        });
+        Some(f(unsafe { &mut *ptr }))
+    }
+
+    /// Remove the application data attached to this connection and return it, if it's of type `T`.
+    pub fn take_user_data<T: 'static>(&self) -> Option<T> {
+        self.with_state(|state| state.user_data.take::<T>())
+    }
+
+    /// Get the currently active TX PHY.
+    ///
+    /// This starts as the phy the connection was established on. A central scanning on both the 1Mbps
+    /// and the coded phy can't tell which one that was, the softdevice doesn't report it, so it then
+    /// starts as the 1Mbps phy until the next PHY update.
+    pub fn tx_phy(&self) -> Phy {
+        with_state(self.index, |s| Phy::from_raw(s.tx_phy))
+    }
+
+    /// Get the currently active RX PHY, see [`tx_phy`](Self::tx_phy).
+    pub fn rx_phy(&self) -> Phy {
+        with_state(self.index, |s| Phy::from_raw(s.rx_phy))
+    }
+
     /// Get the currently active ATT MTU.
     #[cfg(feature = "ble-gatt")]
     pub fn att_mtu(&self) -> u16 {
@@ -623,6 +1251,29 @@ impl Connection {
         ConnectionIter(0)
     }
 
+    /// Get a stream of the events happening on this connection.
+    ///
+    /// Only events happening after this call are received, except if the connection is already
+    /// disconnected: the events still queued are received then. The stream always ends with
+    /// [`ConnectionEvent::Disconnected`]. It holds a reference to the connection, so the
+    /// connection is not disconnected on drop while the stream is alive.
+    ///
+    /// Each event is delivered to a single receiver, so only one stream per connection should
+    /// be in use at a time.
+    #[cfg(feature = "ble-conn-events")]
+    pub fn events(&self) -> ConnectionEvents {
+        // Once disconnected no more events are queued, keep the last ones including `Disconnected`.
+        if self.disconnect_reason().is_none() {
+            EVENTS[self.index as usize].clear();
+            #[cfg(feature = "ble-rssi")]
+            self.with_state(|state| state.rssi_event = None);
+        }
+        ConnectionEvents {
+            conn: self.clone(),
+            done: false,
+        }
+    }
+
     /// Initiate a Data Length Update procedure.
     ///
     /// Note that this just initiates the data length update, it does not wait for completion.
@@ -668,6 +1319,61 @@ impl Connection {
         Ok(())
     }
 
+    /// Initiate a Data Length Update procedure and wait for it to complete.
+    ///
+    /// Returns the effective data length parameters, which may be lower than the requested ones
+    /// if the peer doesn't support them. Returns them immediately if they already are the
+    /// requested ones, as the procedure then doesn't report anything.
+    ///
+    /// Returns [`DataLengthUpdateError::Rejected`] if the peer doesn't answer within the
+    /// connection's supervision timeout, and [`DataLengthUpdateError::Busy`] if another task is
+    /// already waiting on this connection.
+    #[cfg(all(
+        any(feature = "s113", feature = "s132", feature = "s140"),
+        feature = "ble-data-length-wait"
+    ))]
+    pub async fn data_length_update_and_wait(
+        &mut self,
+        params: Option<&raw::ble_gap_data_length_params_t>,
+    ) -> Result<raw::ble_gap_data_length_params_t, DataLengthUpdateError> {
+        let (conn_handle, current, sup_timeout) = self.with_state(|state| {
+            let conn_handle = state.check_connected()?;
+            if state.data_length_update_waiting {
+                return Err(DataLengthUpdateError::Busy);
+            }
+            Ok((
+                conn_handle,
+                state.data_length_params,
+                state.conn_params.conn_sup_timeout,
+            ))
+        })?;
+        if params.is_some_and(|params| data_length_matches(params, &current)) {
+            return Ok(current);
+        }
+
+        self.with_state(|state| state.data_length_update_waiting = true);
+        let index = self.index;
+        let _waiting = OnDrop::new(move || with_state(index, |state| state.data_length_update_waiting = false));
+        self.data_length_update(params)?;
+
+        let res = data_length_portal(conn_handle).wait_once(|ble_evt| unsafe {
+            match (*ble_evt).header.evt_id as u32 {
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(DataLengthUpdateError::Disconnected),
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_DATA_LENGTH_UPDATE => {
+                    let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                    Ok(gap_evt.params.data_length_update.effective_params)
+                }
+                e => panic!("unexpected event {}", e),
+            }
+        });
+        // conn_sup_timeout is in 10ms units.
+        let timeout = embassy_time::Timer::after_millis(u64::from(sup_timeout) * 10);
+        match embassy_futures::select::select(res, timeout).await {
+            embassy_futures::select::Either::First(res) => res,
+            embassy_futures::select::Either::Second(()) => Err(DataLengthUpdateError::Rejected),
+        }
+    }
+
     /// Send a request to the connected device to change the PHY.
     ///
     /// Note that this just initiates the PHY change, it does not wait for completion.
@@ -688,6 +1394,59 @@ impl Connection {
         Ok(())
     }
 
+    /// Send a request to the connected device to change the PHY and wait for the procedure to complete.
+    ///
+    /// Returns the negotiated `(tx_phy, rx_phy)`, which may differ from the requested ones, or
+    /// [`PhyUpdateError::Busy`] if another task is already waiting on this connection.
+    pub async fn phy_update_and_wait(
+        &mut self,
+        tx_phys: PhySet,
+        rx_phys: PhySet,
+    ) -> Result<(Phy, Phy), PhyUpdateError> {
+        let conn_handle = self.with_state(|state| {
+            let conn_handle = state.check_connected()?;
+            if state.phy_update_waiting {
+                return Err(PhyUpdateError::Busy);
+            }
+            state.phy_update_waiting = true;
+            Ok(conn_handle)
+        })?;
+        let index = self.index;
+        let _waiting = OnDrop::new(move || with_state(index, |state| state.phy_update_waiting = false));
+        self.phy_update(tx_phys, rx_phys)?;
+
+        phy_portal(conn_handle)
+            .wait_once(|ble_evt| unsafe {
+                match (*ble_evt).header.evt_id as u32 {
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(PhyUpdateError::Disconnected),
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE => {
+                        let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                        let params = gap_evt.params.phy_update;
+                        match HciStatus::new(params.status) {
+                            HciStatus::SUCCESS => Ok((Phy::from_raw(params.tx_phy), Phy::from_raw(params.rx_phy))),
+                            HciStatus::LMP_ERROR_TRANSACTION_COLLISION | HciStatus::DIFFERENT_TRANSACTION_COLLISION => {
+                                Err(PhyUpdateError::Collision)
+                            }
+                            status => Err(PhyUpdateError::Rejected(status)),
+                        }
+                    }
+                    e => panic!("unexpected event {}", e),
+                }
+            })
+            .await
+    }
+
+    /// Switch the connection to the coded phy in both directions, and wait for the procedure to complete.
+    ///
+    /// Returns the negotiated `(tx_phy, rx_phy)`, which are not the coded phy if the peer doesn't support it.
+    ///
+    /// There is no S=2/S=8 coding preference: the softdevice API has no option for it, and always
+    /// transmits with S=8, see [`Phy::Coded`].
+    #[cfg(feature = "s140")]
+    pub async fn phy_update_long_range(&mut self) -> Result<(Phy, Phy), PhyUpdateError> {
+        self.phy_update_and_wait(PhySet::Coded, PhySet::Coded).await
+    }
+
     #[cfg(feature = "ble-central")]
     /// Send a pairing request to the peripheral.
     pub fn request_pairing(&self) -> Result<(), AuthenticateError> {
@@ -780,6 +1539,61 @@ impl Connection {
     }
 }
 
+/// Stream of [`ConnectionEvent`]s, see [`Connection::events`].
+#[cfg(feature = "ble-conn-events")]
+pub struct ConnectionEvents {
+    conn: Connection,
+    done: bool,
+}
+
+#[cfg(feature = "ble-conn-events")]
+impl ConnectionEvents {
+    /// The connection the events belong to.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Wait for the next event. Returns `None` once the connection is disconnected.
+    pub async fn next(&mut self) -> Option<ConnectionEvent> {
+        poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<ConnectionEvent>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(event) = EVENTS[self.conn.index as usize].poll_receive(cx) {
+            self.done = matches!(event, ConnectionEvent::Disconnected(_));
+            return Poll::Ready(Some(event));
+        }
+
+        #[cfg(feature = "ble-rssi")]
+        {
+            RSSI_EVENT[self.conn.index as usize].lock(|waker| waker.borrow_mut().register(cx.waker()));
+            if let Some(rssi) = self.conn.with_state(|state| state.rssi_event.take()) {
+                return Poll::Ready(Some(ConnectionEvent::RssiChanged(rssi)));
+            }
+        }
+
+        // `Disconnected` was received by another stream, or evicted from the queue.
+        if let Some(reason) = self.conn.disconnect_reason() {
+            self.done = true;
+            return Poll::Ready(Some(ConnectionEvent::Disconnected(reason)));
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "ble-conn-events")]
+impl Stream for ConnectionEvents {
+    type Item = ConnectionEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_event(cx)
+    }
+}
+
 pub struct ConnectionIter(u8);
 
 impl Iterator for ConnectionIter {
@@ -841,6 +1655,95 @@ fn allocate_index<T>(f: impl FnOnce(u8, &mut ConnectionState) -> T) -> Result<T,
     }
 }
 
+// Pending ConnectionEvents by index.
+#[cfg(feature = "ble-conn-events")]
+#[allow(clippy::declare_interior_mutable_const)]
+const EVENTS_NEW: Channel<CriticalSectionRawMutex, ConnectionEvent, EVENTS_QUEUE_LEN> = Channel::new();
+#[cfg(feature = "ble-conn-events")]
+static EVENTS: [Channel<CriticalSectionRawMutex, ConnectionEvent, EVENTS_QUEUE_LEN>; CONNS_MAX] =
+    [EVENTS_NEW; CONNS_MAX];
+
+// Tasks awaiting `Connection::disconnected` by index.
+#[allow(clippy::declare_interior_mutable_const)]
+const DISCONNECTED_NEW: Waiters<DISCONNECTED_WAITERS> = Waiters::new();
+static DISCONNECTED: [Waiters<DISCONNECTED_WAITERS>; CONNS_MAX] = [DISCONNECTED_NEW; CONNS_MAX];
+
+// Wakers of RSSI waits by index.
+#[cfg(feature = "ble-rssi")]
+#[allow(clippy::declare_interior_mutable_const)]
+const RSSI_CHANGED_NEW: Waiters<RSSI_WAITERS> = Waiters::new();
+#[cfg(feature = "ble-rssi")]
+static RSSI_CHANGED: [Waiters<RSSI_WAITERS>; CONNS_MAX] = [RSSI_CHANGED_NEW; CONNS_MAX];
+
+// Waker of the `ConnectionEvents` stream waiting for an RSSI event by index. Only one stream is
+// expected per connection, so it doesn't take one of the `RSSI_CHANGED` slots.
+#[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+#[allow(clippy::declare_interior_mutable_const)]
+const RSSI_EVENT_NEW: Mutex<CriticalSectionRawMutex, RefCell<WakerRegistration>> =
+    Mutex::new(RefCell::new(WakerRegistration::new()));
+#[cfg(all(feature = "ble-conn-events", feature = "ble-rssi"))]
+static RSSI_EVENT: [Mutex<CriticalSectionRawMutex, RefCell<WakerRegistration>>; CONNS_MAX] =
+    [RSSI_EVENT_NEW; CONNS_MAX];
+
+#[cfg(feature = "ble-rssi")]
+pub(crate) fn wake_rssi_waiters(conn_handle: u16) {
+    if let Some(index) = index_by_handle(conn_handle).get() {
+        RSSI_CHANGED[index as usize].wake();
+        #[cfg(feature = "ble-conn-events")]
+        RSSI_EVENT[index as usize].lock(|waker| waker.borrow_mut().wake());
+    }
+}
+
+#[cfg(feature = "ble-conn-events")]
+fn push_event(index: u8, event: ConnectionEvent) {
+    let events = &EVENTS[index as usize];
+    if events.is_full() {
+        let _ = events.try_receive();
+    }
+    let _ = events.try_send(event);
+}
+
+#[cfg(feature = "ble-conn-events")]
+pub(crate) fn push_event_by_conn_handle(conn_handle: u16, event: ConnectionEvent) {
+    if let Some(index) = index_by_handle(conn_handle).get() {
+        push_event(index, event);
+    }
+}
+
+// Per-connection portals for the results of PHY and data length update procedures, by conn_handle.
+#[allow(clippy::declare_interior_mutable_const)]
+const PORTAL_NEW: Portal<*const raw::ble_evt_t> = Portal::new();
+static PHY_PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+static DATA_LENGTH_PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+
+// Whether `requested` data length parameters are already in effect. Automatic times
+// (`BLE_GAP_DATA_LENGTH_AUTO`) match any, automatic octets are never known to match.
+#[cfg(all(
+    any(feature = "s113", feature = "s132", feature = "s140"),
+    feature = "ble-data-length-wait"
+))]
+fn data_length_matches(
+    requested: &raw::ble_gap_data_length_params_t,
+    current: &raw::ble_gap_data_length_params_t,
+) -> bool {
+    let auto = raw::BLE_GAP_DATA_LENGTH_AUTO as u16;
+    let time_matches = |requested: u16, current: u16| requested == auto || requested == current;
+    requested.max_tx_octets == current.max_tx_octets
+        && requested.max_rx_octets == current.max_rx_octets
+        && time_matches(requested.max_tx_time_us, current.max_tx_time_us)
+        && time_matches(requested.max_rx_time_us, current.max_rx_time_us)
+}
+
+pub(crate) fn phy_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &PHY_PORTALS[conn_handle as usize]
+}
+
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+pub(crate) fn data_length_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &DATA_LENGTH_PORTALS[conn_handle as usize]
+}
+
 // conn_handle -> index mapping. Used to make stuff go faster
 const INDEX_NONE: Cell<Option<u8>> = Cell::new(None);
 static mut INDEX_BY_HANDLE: [Cell<Option<u8>>; CONNS_MAX] = [INDEX_NONE; CONNS_MAX];
@@ -0,0 +1,266 @@
+//! Scanning and advertising at the same time.
+//!
+//! The softdevice shares the radio between the observer and broadcaster roles, but each of them
+//! stops on its own: advertising ends when a connection is made or on timeout, and scanning when it
+//! times out. Connecting as a central also needs the scanner and the whitelist, which collides with
+//! a running scan. A [`Coordinator`] keeps both roles running across all of this, for devices such
+//! as relays that scan and advertise continuously.
+
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
+use futures::future::pending;
+
+use crate::ble::central::{self, AdvertisementReport, ConnectConfig, ConnectError, ScanError};
+use crate::ble::peripheral::{self, AdvertiseError, Advertiser, ScheduledAdvertisement};
+use crate::ble::scanner::{self, Scanner};
+use crate::ble::Connection;
+use crate::{RawError, Softdevice};
+
+#[derive(Clone, Copy)]
+pub struct Config<'a> {
+    pub scan: scanner::Config<'a>,
+    pub adv: peripheral::Config,
+    /// Delay before trying again to start scanning or advertising after it failed, for example
+    /// with [`RawError::ConnCount`] while all connections are in use.
+    pub retry_delay: Duration,
+}
+
+impl<'a> Default for Config<'a> {
+    fn default() -> Self {
+        Self {
+            scan: scanner::Config::default(),
+            adv: peripheral::Config::default(),
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Returned by [`Coordinator::next`].
+#[allow(clippy::large_enum_variant)]
+pub enum Event {
+    /// An advertising report passing [`scanner::Config::filter`].
+    Report(AdvertisementReport),
+    /// A connection made by connectable advertising.
+    Connected(Connection),
+}
+
+/// Combined status of scanning and advertising, see [`Coordinator::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// `Ok` while scanning, otherwise why scanning is stopped until the next retry.
+    pub scan: Result<(), ScanError>,
+    /// `Ok` while advertising, otherwise why advertising is stopped until the next retry.
+    pub advertise: Result<(), AdvertiseError>,
+}
+
+/// Runs a scan and advertising concurrently, restarting both whenever they stop.
+///
+/// Both keep running between calls to [`next`](Self::next), and advertising during
+/// [`connect`](Self::connect) unless it has to be paused. Reports and connections arriving meanwhile
+/// are kept and returned by the next call, but scanning and advertising are only restarted while
+/// `next` is being awaited. Both are stopped on drop.
+///
+/// The coordinator owns the scanner and the advertiser, so it can't be used together with
+/// [`scanner::start`], [`Advertiser`] or the `scan` and `advertise*` functions.
+pub struct Coordinator<'a> {
+    sd: &'a Softdevice,
+    adv: ScheduledAdvertisement<'a>,
+    config: Config<'a>,
+    scanner: Option<Scanner>,
+    advertiser: Option<Advertiser>,
+    // Connection made by the advertiser before it was stopped, returned by the next call to `next`.
+    connected: Option<Connection>,
+    scan_retry: Option<Instant>,
+    adv_retry: Option<Instant>,
+    status: Status,
+}
+
+impl<'a> Coordinator<'a> {
+    /// Start scanning and advertising `adv`.
+    ///
+    /// Failing to start either of them is not an error, it is retried and reported by
+    /// [`status`](Self::status).
+    pub fn new(sd: &'a Softdevice, adv: ScheduledAdvertisement<'a>, config: Config<'a>) -> Self {
+        let mut this = Self {
+            sd,
+            adv,
+            config,
+            scanner: None,
+            advertiser: None,
+            connected: None,
+            scan_retry: None,
+            adv_retry: None,
+            status: Status {
+                scan: Ok(()),
+                advertise: Ok(()),
+            },
+        };
+        this.restart();
+        this
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Replace the advertisement.
+    ///
+    /// If it has the same type as the current one, only the data is replaced without interrupting
+    /// advertising. Otherwise advertising is restarted.
+    pub fn set_advertisement(&mut self, adv: ScheduledAdvertisement<'a>) {
+        self.adv = adv;
+        if let Some(advertiser) = &self.advertiser {
+            if advertiser.set_advertisement(adv).is_ok() {
+                return;
+            }
+            self.stop_advertising();
+        }
+        self.adv_retry = None;
+        self.restart();
+    }
+
+    /// Wait for the next advertising report or connection.
+    ///
+    /// Scanning and advertising are restarted as needed while waiting.
+    pub async fn next(&mut self) -> Event {
+        if let Some(conn) = self.connected.take() {
+            return Event::Connected(conn);
+        }
+
+        loop {
+            self.restart();
+
+            let scanner = &mut self.scanner;
+            let advertiser = &self.advertiser;
+            let retry = match (self.scan_retry, self.adv_retry) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+            // The advertiser is polled first, so a connection is returned before a report arriving at
+            // the same time. Neither is lost when the other one wins: `Advertiser::run` keeps how
+            // advertising ended until it's returned, by the next call or by `stop_advertising`, and
+            // the scanner queues reports.
+            let res = select3(
+                async {
+                    match advertiser {
+                        Some(advertiser) => advertiser.run().await,
+                        None => pending().await,
+                    }
+                },
+                async {
+                    match scanner {
+                        Some(scanner) => scanner.next().await,
+                        None => pending().await,
+                    }
+                },
+                async {
+                    match retry {
+                        Some(at) => Timer::at(at).await,
+                        None => pending().await,
+                    }
+                },
+            )
+            .await;
+
+            match res {
+                Either3::First(res) => {
+                    self.advertiser = None;
+                    match res {
+                        Ok(conn) => {
+                            // Advertise again right away, not only on the next call.
+                            self.restart();
+                            return Event::Connected(conn);
+                        }
+                        Err(err) => {
+                            debug!("coordinator: advertising ended: {:?}", err);
+                            self.status.advertise = Err(err);
+                            if err != AdvertiseError::Timeout {
+                                self.adv_retry = Some(Instant::now() + self.config.retry_delay);
+                            }
+                        }
+                    }
+                }
+                Either3::Second(Ok(report)) => return Event::Report(report),
+                Either3::Second(Err(err)) => {
+                    debug!("coordinator: scan ended: {:?}", err);
+                    self.scanner = None;
+                    self.status.scan = Err(err);
+                    if err != ScanError::Timeout {
+                        self.scan_retry = Some(Instant::now() + self.config.retry_delay);
+                    }
+                }
+                Either3::Third(()) => {}
+            }
+        }
+    }
+
+    /// Connect to a peripheral as a central.
+    ///
+    /// Scanning is paused while connecting. If the whitelist is in use by advertising with a
+    /// [`FilterPolicy`](peripheral::FilterPolicy) other than `Any`, advertising is paused too.
+    /// Both are restarted afterwards.
+    pub async fn connect(&mut self, config: &ConnectConfig<'_>) -> Result<Connection, ConnectError> {
+        let sd = self.sd;
+        self.scanner = None;
+
+        let mut res = central::connect(sd, config).await;
+        if let Err(ConnectError::Raw(RawError::BleGapWhitelistInUse | RawError::InvalidState)) = res {
+            if self.advertiser.is_some() {
+                debug!("coordinator: pausing advertising to connect");
+                self.stop_advertising();
+                res = central::connect(sd, config).await;
+            }
+        }
+
+        self.scan_retry = None;
+        self.adv_retry = None;
+        self.restart();
+        res
+    }
+
+    // Stop advertising, keeping a connection the advertiser made while `next` wasn't being awaited.
+    fn stop_advertising(&mut self) {
+        if let Some(advertiser) = self.advertiser.take() {
+            if let Some(Ok(conn)) = advertiser.take_ended() {
+                self.connected = Some(conn);
+            }
+        }
+    }
+
+    // Start the stopped roles whose retry delay has elapsed.
+    fn restart(&mut self) {
+        let sd = self.sd;
+        let now = Instant::now();
+
+        if self.scanner.is_none() && self.scan_retry.map_or(true, |at| at <= now) {
+            match scanner::start(sd, &self.config.scan) {
+                Ok(scanner) => {
+                    self.scanner = Some(scanner);
+                    self.scan_retry = None;
+                    self.status.scan = Ok(());
+                }
+                Err(err) => {
+                    self.scan_retry = Some(now + self.config.retry_delay);
+                    self.status.scan = Err(err);
+                }
+            }
+        }
+
+        // Not while a connection is waiting to be returned, there would be nowhere to keep another one.
+        if self.advertiser.is_none() && self.connected.is_none() && self.adv_retry.map_or(true, |at| at <= now) {
+            match Advertiser::start(sd, self.adv, &self.config.adv) {
+                Ok(advertiser) => {
+                    self.advertiser = Some(advertiser);
+                    self.adv_retry = None;
+                    self.status.advertise = Ok(());
+                }
+                Err(err) => {
+                    self.adv_retry = Some(now + self.config.retry_delay);
+                    self.status.advertise = Err(err);
+                }
+            }
+        }
+    }
+}
@@ -20,7 +20,7 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 #[cfg(feature = "ble-central")]
                 Role::Central => central::CONNECT_PORTAL.call(ble_evt),
                 #[cfg(feature = "ble-peripheral")]
-                Role::Peripheral => peripheral::ADV_PORTAL.call(ble_evt),
+                Role::Peripheral => peripheral::ADV_PORTAL.call(ble_evt) || peripheral::on_unhandled_evt(ble_evt),
             };
             if !handled {
                 raw::sd_ble_gap_disconnect(gap_evt.conn_handle, raw::BLE_HCI_REMOTE_USER_TERMINATED_CONNECTION as _);
@@ -28,7 +28,12 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => {
             trace!("on_disconnected conn_handle={:?}", gap_evt.conn_handle);
-            connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| state.on_disconnected(ble_evt));
+            let user_data = connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
+                state.on_disconnected(ble_evt);
+                state.user_data.take_all()
+            });
+            // Dropped outside of the state borrow, as its `Drop` may use the connection.
+            drop(user_data);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONN_PARAM_UPDATE => {
             let conn_params = gap_evt.params.conn_param_update.conn_params;
@@ -45,6 +50,10 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
                 state.conn_params = conn_params;
             });
+            #[cfg(feature = "ble-conn-events")]
+            connection::push_event_by_conn_handle(gap_evt.conn_handle, ConnectionEvent::ParamsUpdated(conn_params));
+            #[cfg(feature = "ble-conn-params")]
+            conn_params::portal(gap_evt.conn_handle).call(ble_evt);
         }
         #[cfg(feature = "ble-central")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONN_PARAM_UPDATE_REQUEST => {
@@ -59,7 +68,21 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 conn_params.slave_latency,
             );
 
-            let ret = raw::sd_ble_gap_conn_param_update(conn_handle, &conn_params);
+            #[cfg(feature = "ble-conn-params")]
+            let accept = connection::with_state_by_conn_handle(conn_handle, |state| {
+                state
+                    .conn_params_bounds
+                    .map_or(true, |bounds| conn_params::within_bounds(&bounds, &conn_params))
+            });
+            #[cfg(not(feature = "ble-conn-params"))]
+            let accept = true;
+
+            let ret = if accept {
+                raw::sd_ble_gap_conn_param_update(conn_handle, &conn_params)
+            } else {
+                debug!("rejecting conn param update request, out of bounds");
+                raw::sd_ble_gap_conn_param_update(conn_handle, core::ptr::null())
+            };
             if let Err(err) = RawError::convert(ret) {
                 warn!("sd_ble_gap_conn_param_update err {:?}", err);
             }
@@ -72,18 +95,30 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 #[cfg(feature = "ble-central")]
                 raw::BLE_GAP_TIMEOUT_SRC_CONN => central::CONNECT_PORTAL.call(ble_evt),
                 #[cfg(feature = "ble-central")]
-                raw::BLE_GAP_TIMEOUT_SRC_SCAN => central::SCAN_PORTAL.call(ble_evt),
+                raw::BLE_GAP_TIMEOUT_SRC_SCAN => {
+                    #[cfg(feature = "ble-scanner")]
+                    if scanner::on_timeout() {
+                        return;
+                    }
+                    central::SCAN_PORTAL.call(ble_evt)
+                }
                 x => panic!("unknown timeout src {:?}", x),
             };
         }
         #[cfg(feature = "ble-peripheral")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_ADV_SET_TERMINATED => {
             trace!("adv_set_termnated");
-            peripheral::ADV_PORTAL.call(ble_evt);
+            if !peripheral::ADV_PORTAL.call(ble_evt) {
+                peripheral::on_unhandled_evt(ble_evt);
+            }
         }
         #[cfg(feature = "ble-central")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_ADV_REPORT => {
             trace!("central on_adv_report");
+            #[cfg(feature = "ble-scanner")]
+            if scanner::on_adv_report(&gap_evt.params.adv_report) {
+                return;
+            }
             central::SCAN_PORTAL.call(ble_evt);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE_REQUEST => {
@@ -109,15 +144,31 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             }
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE => {
-            let _phy_update = gap_evt.params.phy_update;
+            let phy_update = gap_evt.params.phy_update;
 
             trace!(
                 "on_phy_update conn_handle={:?} status={:?} rx_phy={:?} tx_phy={:?}",
                 gap_evt.conn_handle,
-                _phy_update.status,
-                _phy_update.rx_phy,
-                _phy_update.tx_phy
+                phy_update.status,
+                phy_update.rx_phy,
+                phy_update.tx_phy
             );
+
+            if u32::from(phy_update.status) == raw::BLE_HCI_STATUS_CODE_SUCCESS {
+                connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
+                    state.tx_phy = phy_update.tx_phy;
+                    state.rx_phy = phy_update.rx_phy;
+                });
+                #[cfg(feature = "ble-conn-events")]
+                connection::push_event_by_conn_handle(
+                    gap_evt.conn_handle,
+                    ConnectionEvent::PhyUpdated {
+                        tx_phy: Phy::from_raw(phy_update.tx_phy),
+                        rx_phy: Phy::from_raw(phy_update.rx_phy),
+                    },
+                );
+            }
+            connection::phy_portal(gap_evt.conn_handle).call(ble_evt);
         }
         #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_DATA_LENGTH_UPDATE_REQUEST => {
@@ -143,6 +194,10 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
 
             connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
                 state.data_length_effective = effective_params.max_tx_octets as u8;
+                #[cfg(feature = "ble-data-length-wait")]
+                {
+                    state.data_length_params = effective_params;
+                }
             });
 
             debug!(
@@ -153,6 +208,13 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 effective_params.max_tx_octets,
                 effective_params.max_tx_time_us,
             );
+
+            #[cfg(feature = "ble-conn-events")]
+            connection::push_event_by_conn_handle(
+                gap_evt.conn_handle,
+                ConnectionEvent::DataLengthUpdated(effective_params),
+            );
+            connection::data_length_portal(gap_evt.conn_handle).call(ble_evt);
         }
         #[cfg(feature = "ble-rssi")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_RSSI_CHANGED => {
@@ -162,7 +224,13 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                     None => Some(new_rssi),
                     Some(old_rssi) => Some((((old_rssi as i16) * 7 + (new_rssi as i16)) / 8) as i8),
                 };
+                state.rssi_reported = Some(new_rssi);
+                #[cfg(feature = "ble-conn-events")]
+                {
+                    state.rssi_event = Some(new_rssi);
+                }
             });
+            connection::wake_rssi_waiters(gap_evt.conn_handle);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_SEC_PARAMS_REQUEST => {
             let params = &gap_evt.params.sec_params_request;
@@ -263,8 +331,15 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
 
             #[cfg(feature = "ble-sec")]
             let key = Connection::from_handle(gap_evt.conn_handle).and_then(|conn| {
-                conn.security_handler()
-                    .and_then(|x| x.get_key(&conn, MasterId::from_raw(params.master_id)))
+                let key = conn
+                    .security_handler()
+                    .and_then(|x| x.get_key(&conn, MasterId::from_raw(params.master_id)));
+                // The link is encrypted with an existing bond, the handler doesn't give us the identity
+                // address so the one used for this connection is the best we have.
+                if key.is_some() {
+                    conn.with_state(|state| state.bonded_peer = Some(state.peer_address));
+                }
+                key
             });
 
             #[cfg(not(feature = "ble-sec"))]
@@ -298,6 +373,11 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                         handler.on_security_update(&conn, state.security_mode);
                     }
                 });
+                #[cfg(feature = "ble-conn-events")]
+                connection::push_event_by_conn_handle(
+                    gap_evt.conn_handle,
+                    ConnectionEvent::SecurityChanged(conn.security_mode()),
+                );
             }
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_AUTH_STATUS => {
@@ -314,14 +394,15 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             if u32::from(params.auth_status) == raw::BLE_GAP_SEC_STATUS_SUCCESS && params.bonded() != 0 {
                 if let Some(conn) = Connection::from_handle(gap_evt.conn_handle) {
                     conn.with_state(|state| {
-                        if let Some(handler) = state.security.handler {
-                            let peer_id = if params.kdist_peer.id() != 0 {
-                                IdentityKey::from_raw(state.security.peer_id)
-                            } else {
-                                debug!("Peer identity key not distributed; falling back to address");
-                                IdentityKey::from_addr(state.peer_address)
-                            };
+                        let peer_id = if params.kdist_peer.id() != 0 {
+                            IdentityKey::from_raw(state.security.peer_id)
+                        } else {
+                            debug!("Peer identity key not distributed; falling back to address");
+                            IdentityKey::from_addr(state.peer_address)
+                        };
+                        state.bonded_peer = Some(peer_id.addr);
 
+                        if let Some(handler) = state.security.handler {
                             let enc_key = match state.role {
                                 #[cfg(feature = "ble-central")]
                                 Role::Central => &state.security.peer_enc_key,
@@ -369,9 +450,18 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
         }
         // BLE_GAP_EVTS_BLE_GAP_EVT_KEY_PRESSED (LESC central pairing)
         // BLE_GAP_EVTS_BLE_GAP_EVT_LESC_DHKEY_REQUEST (LESC key calculation)
+        #[cfg(any(feature = "s132", feature = "s140"))]
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_QOS_CHANNEL_SURVEY_REPORT => {
+            let report = &gap_evt.params.qos_channel_survey_report;
+            channel_survey::on_report(report);
+        }
+        #[cfg(feature = "ble-peripheral")]
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_SCAN_REQ_REPORT => {
+            let report = &gap_evt.params.scan_req_report;
+            trace!("on_scan_req_report rssi={:?}", report.rssi);
+            peripheral::on_scan_req_report(report);
+        }
         // BLE_GAP_EVTS_BLE_GAP_EVT_RSSI_CHANGED
-        // BLE_GAP_EVTS_BLE_GAP_EVT_SCAN_REQ_REPORT
-        // BLE_GAP_EVTS_BLE_GAP_EVT_QOS_CHANNEL_SURVEY_REPORT
         _ => {}
     }
 }
@@ -440,6 +530,215 @@ pub fn set_whitelist(sd: &Softdevice, addrs: &[Address]) -> Result<(), RawError>
     RawError::convert(ret)
 }
 
+/// Set the device name, exposed in the Device Name characteristic of the GAP service.
+///
+/// `write_perm` is the security required for peers to write the name. Use [`SecurityMode::NoAccess`]
+/// to make it read-only. Writes from peers are reported to the GATT server with `Server::on_device_name_write`.
+///
+/// The maximum length is set by the `gap_device_name` field of the softdevice [`Config`](crate::Config).
+/// Returns [`RawError::DataSize`] if `name` is longer.
+pub fn set_device_name(sd: &Softdevice, name: &str, write_perm: SecurityMode) -> Result<(), RawError> {
+    let _ = sd;
+    if name.len() > raw::BLE_GAP_DEVNAME_MAX_LEN as usize {
+        return Err(RawError::DataSize);
+    }
+    let write_perm = write_perm.into_raw();
+    let ret = unsafe { raw::sd_ble_gap_device_name_set(&write_perm, name.as_ptr(), name.len() as u16) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_device_name_set err {:?}", err);
+        err
+    })
+}
+
+/// Get the current device name into `buf`, returning its length in bytes.
+///
+/// Returns [`RawError::DataSize`] if `buf` is too small to hold the name.
+pub fn get_device_name(sd: &Softdevice, buf: &mut [u8]) -> Result<usize, RawError> {
+    let _ = sd;
+    let mut len = buf.len().min(u16::MAX as usize) as u16;
+    let ret = unsafe { raw::sd_ble_gap_device_name_get(buf.as_mut_ptr(), &mut len) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_device_name_get err {:?}", err);
+        err
+    })?;
+    Ok(usize::from(len))
+}
+
+/// Set the value of the Appearance characteristic of the GAP service.
+pub fn set_appearance(sd: &Softdevice, appearance: Appearance) -> Result<(), RawError> {
+    let _ = sd;
+    let ret = unsafe { raw::sd_ble_gap_appearance_set(appearance.to_u16()) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_appearance_set err {:?}", err);
+        err
+    })
+}
+
+pub fn get_appearance(sd: &Softdevice) -> Result<Appearance, RawError> {
+    let _ = sd;
+    let mut appearance: u16 = 0;
+    let ret = unsafe { raw::sd_ble_gap_appearance_get(&mut appearance) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_appearance_get err {:?}", err);
+        err
+    })?;
+    Ok(Appearance::from_u16(appearance))
+}
+
+/// Set the Peripheral Preferred Connection Parameters (PPCP) characteristic of the GAP service.
+///
+/// Centrals may read these to pick connection parameters. This requires the characteristic to be
+/// included, which is the softdevice default (see the `gap_ppcp_incl` field of [`Config`](crate::Config)).
+#[cfg(feature = "ble-peripheral")]
+pub fn set_preferred_conn_params(sd: &Softdevice, conn_params: &raw::ble_gap_conn_params_t) -> Result<(), RawError> {
+    let _ = sd;
+    let ret = unsafe { raw::sd_ble_gap_ppcp_set(conn_params) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_ppcp_set err {:?}", err);
+        err
+    })
+}
+
+#[cfg(feature = "ble-peripheral")]
+pub fn get_preferred_conn_params(sd: &Softdevice) -> Result<raw::ble_gap_conn_params_t, RawError> {
+    let _ = sd;
+    let mut conn_params: raw::ble_gap_conn_params_t = unsafe { core::mem::zeroed() };
+    let ret = unsafe { raw::sd_ble_gap_ppcp_get(&mut conn_params) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_ppcp_get err {:?}", err);
+        err
+    })?;
+    Ok(conn_params)
+}
+
+/// Set the data channel map used by connections.
+///
+/// The softdevice applies it to all current and future connections in the central role, it
+/// can't be set per connection. At least two channels must be enabled. Use
+/// [`Connection::channel_map`] to see when it has taken effect on a connection.
+#[cfg(feature = "ble-central")]
+pub fn set_channel_map(sd: &Softdevice, channel_map: &ChannelMap) -> Result<(), RawError> {
+    let _ = sd;
+    let ret = unsafe {
+        raw::sd_ble_opt_set(
+            raw::BLE_GAP_OPTS_BLE_GAP_OPT_CH_MAP,
+            &raw::ble_opt_t {
+                gap_opt: raw::ble_gap_opt_t {
+                    ch_map: raw::ble_gap_opt_ch_map_t {
+                        conn_handle: 0,
+                        ch_map: channel_map.to_raw(),
+                    },
+                },
+            },
+        )
+    };
+    RawError::convert(ret).map_err(|err| {
+        warn!("set_channel_map sd_ble_opt_set err {:?}", err);
+        err
+    })
+}
+
+#[repr(u8)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PrivacyMode {
+    /// Privacy is disabled, the identity address is used over the air.
+    #[default]
+    Off = raw::BLE_GAP_PRIVACY_MODE_OFF as u8,
+    /// Use private addresses, and accept peers using either their identity or private addresses.
+    DevicePrivacy = raw::BLE_GAP_PRIVACY_MODE_DEVICE_PRIVACY as u8,
+    /// Use private addresses, and only accept peers in the device identities list using private addresses.
+    NetworkPrivacy = raw::BLE_GAP_PRIVACY_MODE_NETWORK_PRIVACY as u8,
+}
+
+#[repr(u8)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PrivateAddressType {
+    /// Random private resolvable address, which bonded peers can resolve using the local IRK.
+    #[default]
+    Resolvable = raw::BLE_GAP_ADDR_TYPE_RANDOM_PRIVATE_RESOLVABLE as u8,
+    /// Random private non-resolvable address.
+    NonResolvable = raw::BLE_GAP_ADDR_TYPE_RANDOM_PRIVATE_NON_RESOLVABLE as u8,
+}
+
+/// Local privacy configuration, see [`set_privacy`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Privacy {
+    pub mode: PrivacyMode,
+    pub address_type: PrivateAddressType,
+
+    /// Private address cycle interval, in seconds. If set to 0, the softdevice default of 900s (15 minutes) is used.
+    pub cycle_interval: u16,
+
+    /// Local IRK used to generate resolvable private addresses, and distributed to peers when bonding.
+    ///
+    /// If None, the device IRK currently held by the softdevice is kept. The softdevice generates a random one
+    /// when it's enabled, so bonded peers will no longer resolve the device after a reset unless the IRK is
+    /// persisted and provided here.
+    pub irk: Option<IdentityResolutionKey>,
+}
+
+/// Configure local privacy.
+///
+/// When enabled, the softdevice replaces the identity address with a private address that is
+/// rotated every `cycle_interval` seconds, so the device can't be tracked by a fixed address.
+///
+/// The IRK distributed in bonding procedures is the one active when bonding starts, so this should
+/// be called before advertising or connecting.
+pub fn set_privacy(sd: &Softdevice, privacy: &Privacy) -> Result<(), RawError> {
+    let _ = sd;
+    let mut irk = privacy.irk.map(|irk| *irk.as_raw());
+    let params = raw::ble_gap_privacy_params_t {
+        privacy_mode: privacy.mode as u8,
+        private_addr_type: privacy.address_type as u8,
+        private_addr_cycle_s: privacy.cycle_interval,
+        p_device_irk: irk.as_mut().map(|x| x as *mut _).unwrap_or(core::ptr::null_mut()),
+    };
+
+    let ret = unsafe { raw::sd_ble_gap_privacy_set(&params) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_privacy_set err {:?}", err);
+        err
+    })
+}
+
+/// Get the current local privacy configuration, including the active device IRK.
+pub fn get_privacy(sd: &Softdevice) -> Result<Privacy, RawError> {
+    let _ = sd;
+    let mut irk = raw::ble_gap_irk_t { irk: [0; 16] };
+    let mut params = raw::ble_gap_privacy_params_t {
+        privacy_mode: 0,
+        private_addr_type: 0,
+        private_addr_cycle_s: 0,
+        p_device_irk: &mut irk,
+    };
+
+    let ret = unsafe { raw::sd_ble_gap_privacy_get(&mut params) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gap_privacy_get err {:?}", err);
+        err
+    })?;
+
+    let mode = match u32::from(params.privacy_mode) {
+        raw::BLE_GAP_PRIVACY_MODE_DEVICE_PRIVACY => PrivacyMode::DevicePrivacy,
+        raw::BLE_GAP_PRIVACY_MODE_NETWORK_PRIVACY => PrivacyMode::NetworkPrivacy,
+        _ => PrivacyMode::Off,
+    };
+    let address_type = match u32::from(params.private_addr_type) {
+        raw::BLE_GAP_ADDR_TYPE_RANDOM_PRIVATE_NON_RESOLVABLE => PrivateAddressType::NonResolvable,
+        _ => PrivateAddressType::Resolvable,
+    };
+
+    Ok(Privacy {
+        mode,
+        address_type,
+        cycle_interval: params.private_addr_cycle_s,
+        irk: Some(IdentityResolutionKey::from_raw(irk)),
+    })
+}
+
 pub fn default_security_params() -> raw::ble_gap_sec_params_t {
     let mut sec_params: raw::ble_gap_sec_params_t = unsafe { core::mem::zeroed() };
 
@@ -143,6 +143,15 @@ pub trait Server: Sized {
         let _ = conn;
         None
     }
+
+    /// Callback to indicate that the client has written the Device Name characteristic of the GAP service.
+    ///
+    /// The softdevice has already stored the new name, see [`set_device_name`][crate::ble::set_device_name].
+    /// `name` is the complete name, also when the client wrote only part of it at an offset.
+    fn on_device_name_write(&self, conn: &Connection, name: &[u8]) -> Option<Self::Event> {
+        let _ = (conn, name);
+        None
+    }
 }
 
 pub trait Service: Sized {
@@ -210,11 +219,31 @@ where
                     let v = get_flexarray(ble_evt, &params.data, params.len as usize);
                     trace!("gatts write handle={:?} data={:?}", params.handle, v);
 
-                    match params.op.try_into() {
-                        Ok(op) => server.on_write(&conn, params.handle, op, offset, v),
-                        Err(_) => {
-                            error!("gatt_server invalid write op: {}", params.op);
-                            None
+                    if params.uuid.type_ == raw::BLE_UUID_TYPE_BLE as u8
+                        && params.uuid.uuid == raw::BLE_UUID_GAP_CHARACTERISTIC_DEVICE_NAME as u16
+                    {
+                        match params.op.try_into() {
+                            Ok(WriteOp::PrepareWriteRequest | WriteOp::CancelPreparedWrites) | Err(_) => None,
+                            Ok(_) => {
+                                // The write may only cover part of the name, report the whole name stored
+                                // by the softdevice.
+                                let mut name = [0; raw::BLE_GAP_DEVNAME_MAX_LEN as usize];
+                                match get_device_name(Softdevice::steal(), &mut name) {
+                                    Ok(len) => server.on_device_name_write(&conn, &name[..len]),
+                                    Err(err) => {
+                                        warn!("gatt_server failed to get device name: {:?}", err);
+                                        None
+                                    }
+                                }
+                            }
+                        }
+                    } else {
+                        match params.op.try_into() {
+                            Ok(op) => server.on_write(&conn, params.handle, op, offset, v),
+                            Err(_) => {
+                                error!("gatt_server invalid write op: {}", params.op);
+                                None
+                            }
                         }
                     }
                 }
@@ -448,7 +477,8 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             let conn_handle = gatts_evt.conn_handle;
             let params = get_union_field(ble_evt, &gatts_evt.params.exchange_mtu_request);
             let want_mtu = params.client_rx_mtu;
-            let max_mtu = crate::Softdevice::steal().att_mtu;
+            let profile = connection::with_state_by_conn_handle(conn_handle, |state| state.conn_profile);
+            let max_mtu = crate::Softdevice::steal().att_mtu_for(profile);
             let mtu = want_mtu.min(max_mtu).max(raw::BLE_GATT_ATT_MTU_DEFAULT as u16);
             trace!("att mtu exchange: peer wants mtu {:?}, granting {:?}", want_mtu, mtu);
 
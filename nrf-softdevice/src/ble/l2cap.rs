@@ -205,6 +205,7 @@ impl<P: Packet> L2cap<P> {
     /// deems the PSM acceptable.
     pub async fn setup(&self, conn: &Connection, config: &Config, psm: u16) -> Result<Channel<P>, SetupError> {
         let sd = unsafe { Softdevice::steal() };
+        let rx_mps = sd.l2cap_rx_mps_for(conn.conn_profile());
 
         let conn_handle = conn.with_state(|state| state.check_connected())?;
         let mut cid: u16 = raw::BLE_L2CAP_CID_INVALID as _;
@@ -212,7 +213,7 @@ impl<P: Packet> L2cap<P> {
             le_psm: psm,
             status: 0, // only used when responding
             rx_params: raw::ble_l2cap_ch_rx_params_t {
-                rx_mps: sd.l2cap_rx_mps,
+                rx_mps,
                 rx_mtu: P::MTU as u16,
                 sdu_buf: raw::ble_data_t {
                     len: 0,
@@ -289,6 +290,7 @@ impl<P: Packet> L2cap<P> {
         mut accept_psm: impl FnMut(u16) -> bool,
     ) -> Result<(u16, Channel<P>), SetupError> {
         let sd = unsafe { Softdevice::steal() };
+        let rx_mps = sd.l2cap_rx_mps_for(conn.conn_profile());
         let conn_handle = conn.with_state(|state| state.check_connected())?;
 
         portal(conn_handle)
@@ -305,7 +307,7 @@ impl<P: Packet> L2cap<P> {
                                 le_psm: evt.le_psm,
                                 status: raw::BLE_L2CAP_CH_STATUS_CODE_SUCCESS as _,
                                 rx_params: raw::ble_l2cap_ch_rx_params_t {
-                                    rx_mps: sd.l2cap_rx_mps,
+                                    rx_mps,
                                     rx_mtu: P::MTU as u16,
                                     sdu_buf: raw::ble_data_t {
                                         len: 0,
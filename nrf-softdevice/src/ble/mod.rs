@@ -16,10 +16,12 @@ mod common;
 #[cfg(feature = "ble-sec")]
 pub mod security;
 
+#[cfg(feature = "ble-central")]
+pub mod advertisement_parser;
 #[cfg(feature = "ble-central")]
 pub mod central;
 
-#[cfg(feature = "ble-peripheral")]
+#[cfg(any(feature = "ble-central", feature = "ble-peripheral"))]
 pub mod advertisement_builder;
 #[cfg(feature = "ble-peripheral")]
 pub mod peripheral;
@@ -33,6 +35,24 @@ pub mod gatt_server;
 #[cfg(feature = "ble-l2cap")]
 pub mod l2cap;
 
+#[cfg(feature = "ble-conn-params")]
+pub mod conn_params;
+
+#[cfg(feature = "ble-scanner")]
+pub mod scanner;
+
+#[cfg(all(feature = "ble-scanner", feature = "ble-peripheral"))]
+pub mod coordinator;
+
+#[cfg(feature = "ble-peripheral-manager")]
+pub mod peripheral_manager;
+
+#[cfg(feature = "ble-rssi")]
+pub mod tx_power;
+
+#[cfg(any(feature = "s132", feature = "s140"))]
+pub mod channel_survey;
+
 use core::mem;
 
 #[cfg(any(feature = "ble-gatt-server", feature = "ble-sec"))]
@@ -1,10 +1,20 @@
 //! Bluetooth Peripheral operations. Peripheral devices emit advertisements, and optionally accept connections from Central devices.
 
+use core::cell::{Cell, RefCell};
+use core::pin::Pin;
 use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
 
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use futures::Stream;
+
+use crate::ble::advertisement_builder::LegacyAdvertisementData;
 use crate::ble::*;
 use crate::util::{get_union_field, OnDrop, Portal};
-use crate::{raw, RawError, Softdevice};
+use crate::{raw, ConnProfileId, RawError, Softdevice};
 
 struct RawAdvertisement<'a> {
     kind: u8,
@@ -20,8 +30,8 @@ struct RawAdvertisement<'a> {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectableAdvertisement<'a> {
     ScannableUndirected {
-        adv_data: &'a [u8],
-        scan_data: &'a [u8],
+        adv_data: &'a LegacyAdvertisementData,
+        scan_data: &'a LegacyAdvertisementData,
     },
     NonscannableDirected {
         peer: Address,
@@ -47,8 +57,8 @@ impl<'a> From<ConnectableAdvertisement<'a>> for RawAdvertisement<'a> {
         match val {
             ConnectableAdvertisement::ScannableUndirected { adv_data, scan_data } => RawAdvertisement {
                 kind: raw::BLE_GAP_ADV_TYPE_CONNECTABLE_SCANNABLE_UNDIRECTED as u8,
-                adv_data: Some(adv_data),
-                scan_data: Some(scan_data),
+                adv_data: Some(adv_data.as_bytes()),
+                scan_data: Some(scan_data.as_bytes()),
                 peer: None,
                 anonymous: false,
                 set_id: 0,
@@ -97,11 +107,11 @@ impl<'a> From<ConnectableAdvertisement<'a>> for RawAdvertisement<'a> {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum NonconnectableAdvertisement<'a> {
     ScannableUndirected {
-        adv_data: &'a [u8],
-        scan_data: &'a [u8],
+        adv_data: &'a LegacyAdvertisementData,
+        scan_data: &'a LegacyAdvertisementData,
     },
     NonscannableUndirected {
-        adv_data: &'a [u8],
+        adv_data: &'a LegacyAdvertisementData,
     },
     #[cfg(any(feature = "s132", feature = "s140"))]
     ExtendedScannableUndirected {
@@ -134,15 +144,15 @@ impl<'a> From<NonconnectableAdvertisement<'a>> for RawAdvertisement<'a> {
         match val {
             NonconnectableAdvertisement::ScannableUndirected { adv_data, scan_data } => RawAdvertisement {
                 kind: raw::BLE_GAP_ADV_TYPE_NONCONNECTABLE_SCANNABLE_UNDIRECTED as _,
-                adv_data: Some(adv_data),
-                scan_data: Some(scan_data),
+                adv_data: Some(adv_data.as_bytes()),
+                scan_data: Some(scan_data.as_bytes()),
                 peer: None,
                 anonymous: false,
                 set_id: 0,
             },
             NonconnectableAdvertisement::NonscannableUndirected { adv_data } => RawAdvertisement {
                 kind: raw::BLE_GAP_ADV_TYPE_NONCONNECTABLE_NONSCANNABLE_UNDIRECTED as _,
-                adv_data: Some(adv_data),
+                adv_data: Some(adv_data.as_bytes()),
                 scan_data: None,
                 peer: None,
                 anonymous: false,
@@ -207,6 +217,11 @@ impl<'a> From<NonconnectableAdvertisement<'a>> for RawAdvertisement<'a> {
 pub enum AdvertiseError {
     Timeout,
     NoFreeConn,
+    /// The primary phy is 2Mbps, or the coded phy was requested for a legacy advertisement type. On
+    /// s140 this is also returned when connectable advertising on the coded phy with a connection
+    /// profile whose event length is too short for it, see
+    /// [`ConnProfile::long_range`](crate::ConnProfile::long_range).
+    InvalidPhy,
     Raw(RawError),
 }
 
@@ -219,7 +234,74 @@ impl From<RawError> for AdvertiseError {
 static mut ADV_HANDLE: u8 = raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8;
 pub(crate) static ADV_PORTAL: Portal<*const raw::ble_evt_t> = Portal::new();
 
+// Set while the advertising set is in use by an `advertise*` function, an `AdvertisingScheduler` or
+// an `Advertiser`. They all wait on `ADV_PORTAL`, which only supports one waiter at a time.
+static ADV_TAKEN: AtomicBool = AtomicBool::new(false);
+
+// Releases the advertising set on drop, see `take_adv`.
+struct AdvTaken;
+
+fn take_adv() -> Result<AdvTaken, AdvertiseError> {
+    if ADV_TAKEN.swap(true, Ordering::AcqRel) {
+        warn!("advertising already running");
+        return Err(AdvertiseError::Raw(RawError::InvalidState));
+    }
+    Ok(AdvTaken)
+}
+
+impl Drop for AdvTaken {
+    fn drop(&mut self) {
+        ADV_TAKEN.store(false, Ordering::Release);
+    }
+}
+
+fn map_data(data: Option<&[u8]>) -> raw::ble_data_t {
+    if let Some(data) = data {
+        assert!(data.len() < u16::MAX as usize);
+        raw::ble_data_t {
+            p_data: data.as_ptr() as _,
+            len: data.len() as u16,
+        }
+    } else {
+        raw::ble_data_t {
+            p_data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+fn check_phys(adv: &RawAdvertisement<'_>, config: &Config) -> Result<(), AdvertiseError> {
+    if config.primary_phy == Phy::M2 {
+        warn!("the primary advertising phy can't be 2Mbps");
+        return Err(AdvertiseError::InvalidPhy);
+    }
+
+    #[cfg(feature = "s140")]
+    if config.primary_phy == Phy::Coded || config.secondary_phy == Phy::Coded {
+        let kind = u32::from(adv.kind);
+        if kind < raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_UNDIRECTED {
+            warn!("the coded phy requires an extended advertisement type");
+            return Err(AdvertiseError::InvalidPhy);
+        }
+        let connectable = matches!(
+            kind,
+            raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_UNDIRECTED
+                | raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_DIRECTED
+        );
+        let sd = unsafe { Softdevice::steal() };
+        if connectable && config.secondary_phy == Phy::Coded && !sd.supports_coded_phy(config.conn_profile) {
+            warn!("connection profile event length too short for the coded phy");
+            return Err(AdvertiseError::InvalidPhy);
+        }
+    }
+    let _ = adv;
+
+    Ok(())
+}
+
 fn start_adv(adv: RawAdvertisement<'_>, config: &Config) -> Result<(), AdvertiseError> {
+    check_phys(&adv, config)?;
+
     let mut adv_params: raw::ble_gap_adv_params_t = unsafe { core::mem::zeroed() };
 
     adv_params.properties.type_ = adv.kind;
@@ -233,22 +315,8 @@ fn start_adv(adv: RawAdvertisement<'_>, config: &Config) -> Result<(), Advertise
     adv_params.interval = config.interval;
     adv_params.filter_policy = config.filter_policy as u8;
     adv_params.set_set_id(adv.set_id);
-    // Unsupported: channel_mask and scan_req_notification
-
-    let map_data = |data: Option<&[u8]>| {
-        if let Some(data) = data {
-            assert!(data.len() < u16::MAX as usize);
-            raw::ble_data_t {
-                p_data: data.as_ptr() as _,
-                len: data.len() as u16,
-            }
-        } else {
-            raw::ble_data_t {
-                p_data: ptr::null_mut(),
-                len: 0,
-            }
-        }
-    };
+    adv_params.set_scan_req_notification(u8::from(config.scan_request_notification));
+    adv_params.channel_mask = config.channel_mask;
 
     let datas = raw::ble_gap_adv_data_t {
         adv_data: map_data(adv.adv_data),
@@ -274,7 +342,7 @@ fn start_adv(adv: RawAdvertisement<'_>, config: &Config) -> Result<(), Advertise
         err
     })?;
 
-    let ret = unsafe { raw::sd_ble_gap_adv_start(ADV_HANDLE, 1u8) };
+    let ret = unsafe { raw::sd_ble_gap_adv_start(ADV_HANDLE, config.conn_profile.conn_cfg_tag()) };
     RawError::convert(ret).map_err(|err| {
         warn!("sd_ble_gap_adv_start err {:?}", err);
         err
@@ -289,6 +357,7 @@ pub async fn advertise(
     adv: NonconnectableAdvertisement<'_>,
     config: &Config,
 ) -> Result<(), AdvertiseError> {
+    let _taken = take_adv()?;
     let d = OnDrop::new(|| {
         let ret = unsafe { raw::sd_ble_gap_adv_stop(ADV_HANDLE) };
         if let Err(_e) = RawError::convert(ret) {
@@ -339,11 +408,12 @@ async fn advertise_inner<'a, F>(
     _sd: &'a Softdevice,
     adv: ConnectableAdvertisement<'a>,
     config: &'a Config,
-    mut f: F,
+    f: F,
 ) -> Result<Connection, AdvertiseError>
 where
     F: FnMut(u16, Role, Address, raw::ble_gap_conn_params_t) -> Result<Connection, OutOfConnsError>,
 {
+    let _taken = take_adv()?;
     let d = OnDrop::new(|| {
         let ret = unsafe { raw::sd_ble_gap_adv_stop(ADV_HANDLE) };
         if let Err(_e) = RawError::convert(ret) {
@@ -351,43 +421,462 @@ where
         }
     });
 
-    start_adv(adv.into(), config)?;
+    let adv = RawAdvertisement::from(adv);
+    let phy = config.conn_phy(adv.kind);
+    start_adv(adv, config)?;
 
     // The advertising data needs to be kept alive for the entire duration of the advertising procedure.
-    let res = ADV_PORTAL
-        .wait_once(|ble_evt| unsafe {
-            match (*ble_evt).header.evt_id as u32 {
-                raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONNECTED => {
-                    let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
-                    let params = &gap_evt.params.connected;
-                    let conn_handle = gap_evt.conn_handle;
-                    let role = Role::from_raw(params.role);
-                    let peer_address = Address::from_raw(params.peer_addr);
-                    let conn_params = params.conn_params;
-                    debug!("connected role={:?} peer_addr={:?}", role, peer_address);
-
-                    match f(conn_handle, role, peer_address, conn_params) {
-                        Ok(conn) => Ok(conn),
-                        Err(_) => {
-                            raw::sd_ble_gap_disconnect(
-                                conn_handle,
-                                raw::BLE_HCI_REMOTE_DEV_TERMINATION_DUE_TO_LOW_RESOURCES as _,
-                            );
-                            Err(AdvertiseError::NoFreeConn)
-                        }
-                    }
-                }
-                raw::BLE_GAP_EVTS_BLE_GAP_EVT_TIMEOUT => Err(AdvertiseError::Timeout),
-                raw::BLE_GAP_EVTS_BLE_GAP_EVT_ADV_SET_TERMINATED => Err(AdvertiseError::Timeout),
-                e => panic!("unexpected event {}", e),
-            }
-        })
-        .await;
+    let res = wait_connection(config.conn_profile, config.tx_power, phy, f).await;
 
     d.defuse();
     res
 }
 
+// Wait for advertising to end, returning the connection if it ended because one was established.
+//
+// `tx_power` is the advertising TX power, which the connection inherits, and `phy` the phy it's
+// established on.
+async fn wait_connection<F>(
+    conn_profile: ConnProfileId,
+    tx_power: TxPower,
+    phy: Phy,
+    mut f: F,
+) -> Result<Connection, AdvertiseError>
+where
+    F: FnMut(u16, Role, Address, raw::ble_gap_conn_params_t) -> Result<Connection, OutOfConnsError>,
+{
+    ADV_PORTAL
+        .wait_once(|ble_evt| unsafe { on_adv_end(ble_evt, conn_profile, tx_power, phy, &mut f) })
+        .await
+}
+
+// Handle an event ending advertising, see `wait_connection`.
+unsafe fn on_adv_end<F>(
+    ble_evt: *const raw::ble_evt_t,
+    conn_profile: ConnProfileId,
+    tx_power: TxPower,
+    phy: Phy,
+    mut f: F,
+) -> Result<Connection, AdvertiseError>
+where
+    F: FnMut(u16, Role, Address, raw::ble_gap_conn_params_t) -> Result<Connection, OutOfConnsError>,
+{
+    match (*ble_evt).header.evt_id as u32 {
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONNECTED => {
+            let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+            let params = &gap_evt.params.connected;
+            let conn_handle = gap_evt.conn_handle;
+            let role = Role::from_raw(params.role);
+            let peer_address = Address::from_raw(params.peer_addr);
+            let conn_params = params.conn_params;
+            debug!("connected role={:?} peer_addr={:?}", role, peer_address);
+
+            match f(conn_handle, role, peer_address, conn_params) {
+                Ok(conn) => {
+                    conn.with_state(|state| {
+                        state.conn_profile = conn_profile;
+                        state.tx_power = tx_power;
+                        state.tx_phy = phy as u8;
+                        state.rx_phy = phy as u8;
+                    });
+                    Ok(conn)
+                }
+                Err(_) => {
+                    raw::sd_ble_gap_disconnect(
+                        conn_handle,
+                        raw::BLE_HCI_REMOTE_DEV_TERMINATION_DUE_TO_LOW_RESOURCES as _,
+                    );
+                    Err(AdvertiseError::NoFreeConn)
+                }
+            }
+        }
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_TIMEOUT => Err(AdvertiseError::Timeout),
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_ADV_SET_TERMINATED => Err(AdvertiseError::Timeout),
+        e => panic!("unexpected event {}", e),
+    }
+}
+
+/// Maximum length of the advertising data and of the scan response data of an [`Advertiser`].
+#[cfg(any(feature = "s132", feature = "s140"))]
+pub const ADVERTISER_DATA_LEN: usize = raw::BLE_GAP_ADV_SET_DATA_SIZE_EXTENDED_MAX_SUPPORTED as usize;
+/// Maximum length of the advertising data and of the scan response data of an [`Advertiser`].
+#[cfg(not(any(feature = "s132", feature = "s140")))]
+pub const ADVERTISER_DATA_LEN: usize = raw::BLE_GAP_ADV_SET_DATA_SIZE_MAX as usize;
+
+// Advertising and scan response data of the `Advertiser`, two buffers each. The softdevice keeps
+// reading the buffers in use while advertising, so updates are written to the other ones.
+static mut ADVERTISER_ADV_DATA: [[u8; ADVERTISER_DATA_LEN]; 2] = [[0; ADVERTISER_DATA_LEN]; 2];
+static mut ADVERTISER_SCAN_DATA: [[u8; ADVERTISER_DATA_LEN]; 2] = [[0; ADVERTISER_DATA_LEN]; 2];
+
+// Settings of the running `Advertiser`, and how its advertising ended until `Advertiser::run`
+// returns it.
+struct AdvertiserState {
+    conn_profile: ConnProfileId,
+    tx_power: TxPower,
+    phy: Phy,
+    ended: Option<Result<Connection, AdvertiseError>>,
+}
+
+static ADVERTISER: Mutex<CriticalSectionRawMutex, RefCell<Option<AdvertiserState>>> = Mutex::new(RefCell::new(None));
+
+/// Handle an event ending the [`Advertiser`]'s advertising, keeping how it ended until
+/// [`Advertiser::run`] returns it. Returns false if no advertiser is running.
+pub(crate) unsafe fn on_unhandled_evt(ble_evt: *const raw::ble_evt_t) -> bool {
+    let Some((conn_profile, tx_power, phy)) =
+        ADVERTISER.lock(|state| state.borrow().as_ref().map(|s| (s.conn_profile, s.tx_power, s.phy)))
+    else {
+        return false;
+    };
+
+    let res = on_adv_end(ble_evt, conn_profile, tx_power, phy, Connection::new);
+    ADVERTISER.lock(|state| {
+        if let Some(state) = state.borrow_mut().as_mut() {
+            state.ended = Some(res);
+        }
+    });
+    true
+}
+
+// Maximum length of the advertising data and of the scan response data for advertisement type `kind`.
+fn max_data_len(kind: u8) -> usize {
+    #[cfg(any(feature = "s132", feature = "s140"))]
+    match u32::from(kind) {
+        raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_UNDIRECTED
+        | raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_DIRECTED => {
+            return raw::BLE_GAP_ADV_SET_DATA_SIZE_EXTENDED_CONNECTABLE_MAX_SUPPORTED as usize;
+        }
+        kind if kind >= raw::BLE_GAP_ADV_TYPE_EXTENDED_NONCONNECTABLE_SCANNABLE_UNDIRECTED => {
+            return ADVERTISER_DATA_LEN;
+        }
+        _ => {}
+    }
+    let _ = kind;
+    raw::BLE_GAP_ADV_SET_DATA_SIZE_MAX as usize
+}
+
+/// Advertising whose data can be changed while it's running.
+///
+/// Unlike the `advertise*` functions, the advertiser owns a copy of the advertising data, so it can be
+/// replaced with [`set_data`](Self::set_data) without stopping advertising. Advertising is stopped on drop.
+///
+/// Only one advertiser can exist at a time. While it exists, the `advertise*` functions and
+/// [`AdvertisingScheduler`] fail with [`RawError::InvalidState`].
+pub struct Advertiser {
+    // Index of the buffers in use by the softdevice.
+    current: Cell<usize>,
+    kind: u8,
+    has_adv_data: bool,
+    has_scan_data: bool,
+    _taken: AdvTaken,
+}
+
+impl Advertiser {
+    /// Start connectable advertising. Connections are returned by [`run`](Self::run).
+    pub fn start_connectable(
+        sd: &Softdevice,
+        adv: ConnectableAdvertisement<'_>,
+        config: &Config,
+    ) -> Result<Self, AdvertiseError> {
+        let _ = sd;
+        Self::start_raw(adv.into(), config)
+    }
+
+    /// Start non-connectable advertising.
+    pub fn start_nonconnectable(
+        sd: &Softdevice,
+        adv: NonconnectableAdvertisement<'_>,
+        config: &Config,
+    ) -> Result<Self, AdvertiseError> {
+        let _ = sd;
+        Self::start_raw(adv.into(), config)
+    }
+
+    /// Start connectable or non-connectable advertising.
+    pub fn start(sd: &Softdevice, adv: ScheduledAdvertisement<'_>, config: &Config) -> Result<Self, AdvertiseError> {
+        let _ = sd;
+        Self::start_raw(adv.into(), config)
+    }
+
+    fn start_raw(adv: RawAdvertisement<'_>, config: &Config) -> Result<Self, AdvertiseError> {
+        // From here on, dropping `this` releases the advertising set again.
+        let this = Self {
+            current: Cell::new(0),
+            kind: adv.kind,
+            has_adv_data: adv.adv_data.is_some(),
+            has_scan_data: adv.scan_data.is_some(),
+            _taken: take_adv()?,
+        };
+        let [adv_data, scan_data] = this.fill(0, adv.adv_data.unwrap_or(&[]), adv.scan_data.unwrap_or(&[]))?;
+        start_adv(
+            RawAdvertisement {
+                adv_data,
+                scan_data,
+                ..adv
+            },
+            config,
+        )?;
+
+        ADVERTISER.lock(|state| {
+            *state.borrow_mut() = Some(AdvertiserState {
+                conn_profile: config.conn_profile,
+                tx_power: config.tx_power,
+                phy: config.conn_phy(this.kind),
+                ended: None,
+            })
+        });
+        Ok(this)
+    }
+
+    // Copy data into buffers `index`, returning the advertising and scan response data in use.
+    fn fill(
+        &self,
+        index: usize,
+        adv_data: &[u8],
+        scan_data: &[u8],
+    ) -> Result<[Option<&'static [u8]>; 2], AdvertiseError> {
+        let max_len = max_data_len(self.kind);
+        if adv_data.len() > max_len || scan_data.len() > max_len {
+            warn!(
+                "advertising data too long, the advertisement type allows {:?} bytes",
+                max_len
+            );
+            return Err(AdvertiseError::Raw(RawError::DataSize));
+        }
+
+        let adv_bufs = &raw mut ADVERTISER_ADV_DATA;
+        let scan_bufs = &raw mut ADVERTISER_SCAN_DATA;
+        // Safety: the softdevice is not using buffers `index`, and only the advertiser writes to them.
+        let (adv_buf, scan_buf): (&'static mut [u8], &'static mut [u8]) = unsafe {
+            (
+                &mut (&mut *adv_bufs)[index][..adv_data.len()],
+                &mut (&mut *scan_bufs)[index][..scan_data.len()],
+            )
+        };
+        adv_buf.copy_from_slice(adv_data);
+        scan_buf.copy_from_slice(scan_data);
+
+        Ok([
+            self.has_adv_data.then_some(adv_buf),
+            self.has_scan_data.then_some(scan_buf),
+        ])
+    }
+
+    /// Replace the advertising and scan response data, without interrupting advertising.
+    ///
+    /// Data not used by the advertisement type started with is ignored, for example `scan_data`
+    /// for non-scannable advertising. Legacy advertisement types take at most 31 bytes of each,
+    /// longer data fails with [`RawError::DataSize`].
+    pub fn set_data(&self, adv_data: &[u8], scan_data: &[u8]) -> Result<(), AdvertiseError> {
+        let next = 1 - self.current.get();
+        let [adv_data, scan_data] = self.fill(next, adv_data, scan_data)?;
+
+        let datas = raw::ble_gap_adv_data_t {
+            adv_data: map_data(adv_data),
+            scan_rsp_data: map_data(scan_data),
+        };
+        let ret = unsafe { raw::sd_ble_gap_adv_set_configure(ptr::addr_of_mut!(ADV_HANDLE), &datas, ptr::null()) };
+        RawError::convert(ret).map_err(|err| {
+            warn!("sd_ble_gap_adv_set_configure err {:?}", err);
+            err
+        })?;
+
+        self.current.set(next);
+        Ok(())
+    }
+
+    /// Replace the data with that of `adv`, without interrupting advertising.
+    ///
+    /// `adv` must be of the same type the advertiser was started with, otherwise this fails with
+    /// [`RawError::InvalidState`].
+    pub fn set_advertisement(&self, adv: ScheduledAdvertisement<'_>) -> Result<(), AdvertiseError> {
+        let adv = RawAdvertisement::from(adv);
+        if adv.kind != self.kind {
+            return Err(AdvertiseError::Raw(RawError::InvalidState));
+        }
+        self.set_data(adv.adv_data.unwrap_or(&[]), adv.scan_data.unwrap_or(&[]))
+    }
+
+    /// Change the TX power, without interrupting advertising.
+    pub fn set_tx_power(&self, tx_power: TxPower) -> Result<(), AdvertiseError> {
+        let ret = unsafe {
+            raw::sd_ble_gap_tx_power_set(
+                raw::BLE_GAP_TX_POWER_ROLES_BLE_GAP_TX_POWER_ROLE_ADV as _,
+                ADV_HANDLE as _,
+                tx_power as i8,
+            )
+        };
+        RawError::convert(ret).map_err(|err| {
+            warn!("sd_ble_gap_tx_power_set err {:?}", err);
+            err
+        })?;
+        ADVERTISER.lock(|state| {
+            if let Some(state) = state.borrow_mut().as_mut() {
+                state.tx_power = tx_power;
+            }
+        });
+        Ok(())
+    }
+
+    /// Wait for advertising to end, returning the connection for connectable advertising.
+    ///
+    /// If advertising ended while this wasn't being awaited, for example because of a connection,
+    /// that result is returned right away. It can run concurrently with [`set_data`](Self::set_data)
+    /// and [`set_tx_power`](Self::set_tx_power), for example with `select`. Cancelling it never
+    /// loses a connection, it is returned by the next call.
+    pub async fn run(&self) -> Result<Connection, AdvertiseError> {
+        loop {
+            if let Some(res) = self.take_ended() {
+                return res;
+            }
+            // How advertising ended is kept in `ADVERTISER` rather than returned by the portal, where
+            // it would be lost if this is dropped between the event and the next poll.
+            ADV_PORTAL
+                .wait_once(|ble_evt| unsafe { on_unhandled_evt(ble_evt) })
+                .await;
+        }
+    }
+
+    // Take how advertising ended, if it did and `run` didn't return it yet.
+    pub(crate) fn take_ended(&self) -> Option<Result<Connection, AdvertiseError>> {
+        ADVERTISER.lock(|state| state.borrow_mut().as_mut().and_then(|state| state.ended.take()))
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        let ret = unsafe { raw::sd_ble_gap_adv_stop(ADV_HANDLE) };
+        match RawError::convert(ret) {
+            Ok(()) => {}
+            Err(RawError::InvalidState) => {} // advertising already ended, erroring is normal.
+            Err(_e) => warn!("sd_ble_gap_adv_stop: {:?}", _e),
+        }
+        // A connection not returned by `run` is dropped outside of the lock, disconnecting it.
+        let state = ADVERTISER.lock(|state| state.borrow_mut().take());
+        drop(state);
+    }
+}
+
+/// An advertisement in an [`AdvertisingScheduler`] rotation.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScheduledAdvertisement<'a> {
+    Connectable(ConnectableAdvertisement<'a>),
+    Nonconnectable(NonconnectableAdvertisement<'a>),
+}
+
+impl<'a> From<ScheduledAdvertisement<'a>> for RawAdvertisement<'a> {
+    fn from(val: ScheduledAdvertisement<'a>) -> RawAdvertisement<'a> {
+        match val {
+            ScheduledAdvertisement::Connectable(adv) => adv.into(),
+            ScheduledAdvertisement::Nonconnectable(adv) => adv.into(),
+        }
+    }
+}
+
+/// How long an entry of an [`AdvertisingScheduler`] is advertised before switching to the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Slot {
+    /// Number of advertising events.
+    Events(u8),
+    /// Duration, in 10ms units.
+    Timeout(u16),
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScheduleEntry<'a> {
+    pub adv: ScheduledAdvertisement<'a>,
+    pub slot: Slot,
+}
+
+/// Rotates several advertisements through the single advertising set of the softdevice.
+///
+/// Each entry is advertised for its [`Slot`], then the next one, and so on, giving each payload
+/// a share of the air time proportional to its slot.
+pub struct AdvertisingScheduler<'a> {
+    entries: &'a [ScheduleEntry<'a>],
+    config: Config,
+    next: usize,
+}
+
+impl<'a> AdvertisingScheduler<'a> {
+    /// `config.timeout` and `config.max_events` are overridden by the slot of each entry.
+    pub fn new(entries: &'a [ScheduleEntry<'a>], config: Config) -> Self {
+        Self {
+            entries,
+            config,
+            next: 0,
+        }
+    }
+
+    /// Run the rotation, calling `on_connection` with connections established on connectable entries.
+    ///
+    /// The rotation continues after a connection. Connectable entries are skipped while there
+    /// are no connections left for `config.conn_profile`. This only returns on error, including
+    /// [`RawError::ConnCount`] when no entry can be advertised.
+    pub async fn run<F>(&mut self, sd: &Softdevice, mut on_connection: F) -> AdvertiseError
+    where
+        F: FnMut(Connection),
+    {
+        let _ = sd;
+        if self.entries.is_empty() {
+            return AdvertiseError::Raw(RawError::InvalidParam);
+        }
+        let _taken = match take_adv() {
+            Ok(taken) => taken,
+            Err(err) => return err,
+        };
+
+        // Number of entries skipped in a row.
+        let mut skipped = 0;
+        loop {
+            let entry = self.entries[self.next];
+            self.next = (self.next + 1) % self.entries.len();
+
+            let mut config = self.config;
+            (config.max_events, config.timeout) = match entry.slot {
+                Slot::Events(events) => (Some(events), None),
+                Slot::Timeout(timeout) => (None, Some(timeout)),
+            };
+
+            let d = OnDrop::new(|| {
+                let ret = unsafe { raw::sd_ble_gap_adv_stop(ADV_HANDLE) };
+                if let Err(_e) = RawError::convert(ret) {
+                    warn!("sd_ble_gap_adv_stop: {:?}", _e);
+                }
+            });
+
+            let adv = RawAdvertisement::from(entry.adv);
+            let phy = config.conn_phy(adv.kind);
+            match start_adv(adv, &config) {
+                Ok(()) => {}
+                Err(AdvertiseError::Raw(RawError::ConnCount)) if skipped + 1 < self.entries.len() => {
+                    d.defuse();
+                    skipped += 1;
+                    continue;
+                }
+                Err(err) => {
+                    d.defuse();
+                    return err;
+                }
+            }
+
+            skipped = 0;
+            let res = wait_connection(config.conn_profile, config.tx_power, phy, Connection::new).await;
+            d.defuse();
+            match res {
+                Ok(conn) => on_connection(conn),
+                // End of the slot.
+                Err(AdvertiseError::Timeout) => {}
+                // The connection is already dropped, keep rotating.
+                Err(AdvertiseError::NoFreeConn) => {}
+                Err(err) => return err,
+            }
+        }
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -413,6 +902,17 @@ pub struct Config {
     pub interval: u32,
 
     pub filter_policy: FilterPolicy,
+
+    /// Report scan requests received while advertising, see [`scan_requests`].
+    pub scan_request_notification: bool,
+
+    /// Channels not to advertise on, as a bit mask of channel indexes (bit 37 is bit 5 of byte 4).
+    /// Only the primary advertising channels 37-39 can be masked, at least one must be left.
+    pub channel_mask: [u8; 5],
+
+    /// Connection profile to use for connectable advertising, see
+    /// [`Config::conn_profiles`](crate::Config::conn_profiles).
+    pub conn_profile: ConnProfileId,
 }
 
 impl Default for Config {
@@ -425,6 +925,91 @@ impl Default for Config {
             max_events: None,
             interval: 400, // 250ms
             filter_policy: FilterPolicy::default(),
+            scan_request_notification: false,
+            channel_mask: [0; 5],
+            conn_profile: ConnProfileId::DEFAULT,
+        }
+    }
+}
+
+impl Config {
+    // Phy of connections made by advertising type `kind` with this config. Connections are made on
+    // the secondary phy for extended advertising, on the 1Mbps phy otherwise.
+    fn conn_phy(&self, kind: u8) -> Phy {
+        #[cfg(any(feature = "s132", feature = "s140"))]
+        if u32::from(kind) >= raw::BLE_GAP_ADV_TYPE_EXTENDED_CONNECTABLE_NONSCANNABLE_UNDIRECTED {
+            return self.secondary_phy;
+        }
+        let _ = kind;
+        Phy::M1
+    }
+
+    /// Advertise on the coded phy, for long range. Only extended advertisement types can be used.
+    ///
+    /// For connectable advertising `conn_profile` must have an event length long enough for the
+    /// coded phy, see [`ConnProfile::long_range`](crate::ConnProfile::long_range).
+    #[cfg(feature = "s140")]
+    pub fn long_range(conn_profile: ConnProfileId) -> Self {
+        Self {
+            primary_phy: Phy::Coded,
+            secondary_phy: Phy::Coded,
+            conn_profile,
+            ..Default::default()
         }
     }
 }
+
+/// A scan request received while advertising, see [`Config::scan_request_notification`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanRequest {
+    /// Address of the scanner.
+    pub peer_address: Address,
+    /// Received signal strength in dBm.
+    pub rssi: i8,
+}
+
+/// Number of scan requests buffered until received from [`ScanRequests`]. Older ones are dropped.
+pub const SCAN_REQUEST_QUEUE_LEN: usize = 8;
+
+static SCAN_REQUESTS: Channel<CriticalSectionRawMutex, ScanRequest, SCAN_REQUEST_QUEUE_LEN> = Channel::new();
+
+pub(crate) fn on_scan_req_report(report: &raw::ble_gap_evt_scan_req_report_t) {
+    if SCAN_REQUESTS.is_full() {
+        let _ = SCAN_REQUESTS.try_receive();
+    }
+    let _ = SCAN_REQUESTS.try_send(ScanRequest {
+        peer_address: Address::from_raw(report.peer_addr),
+        rssi: report.rssi,
+    });
+}
+
+/// Stream of scan requests received while advertising, see [`scan_requests`].
+pub struct ScanRequests {
+    _private: (),
+}
+
+/// Receive the scan requests reported while advertising with [`Config::scan_request_notification`] set.
+///
+/// Advertising continues as usual, this can run concurrently with any of the advertise functions.
+/// Scan requests received before this is called are discarded. There should be only one receiver at a time.
+pub fn scan_requests(sd: &Softdevice) -> ScanRequests {
+    let _ = sd;
+    SCAN_REQUESTS.clear();
+    ScanRequests { _private: () }
+}
+
+impl ScanRequests {
+    /// Wait for the next scan request.
+    pub async fn next(&mut self) -> ScanRequest {
+        SCAN_REQUESTS.receive().await
+    }
+}
+
+impl Stream for ScanRequests {
+    type Item = ScanRequest;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        SCAN_REQUESTS.poll_receive(cx).map(Some)
+    }
+}
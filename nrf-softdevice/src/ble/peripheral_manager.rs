@@ -0,0 +1,231 @@
+//! Connectable advertising for devices accepting several connections.
+//!
+//! A [`PeripheralManager`] restarts advertising after each connection, up to a maximum number of
+//! concurrent links, and implements the usual policies around it:
+//!
+//! - Fast advertising after startup and after each disconnection, then slow advertising.
+//! - Directed high duty cycle advertising to a bonded peer that just disconnected, so it can
+//!   reconnect quickly.
+//! - Backing off when no connection is available.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Poll;
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+use futures::future::poll_fn;
+
+use crate::ble::connection::WeakConnection;
+use crate::ble::peripheral::{self, AdvertiseError, ConnectableAdvertisement};
+use crate::ble::{Address, Connection, HciStatus};
+use crate::{raw, RawError, Softdevice};
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Base advertising configuration. `interval` and `timeout` are overridden by the phases below.
+    pub adv: peripheral::Config,
+    /// Advertising interval during the fast phase, in 625us units.
+    pub fast_interval: u32,
+    /// Duration of the fast phase, after startup and after each disconnection.
+    pub fast_duration: Duration,
+    /// Advertising interval once the fast phase is over, in 625us units.
+    pub slow_interval: u32,
+    /// A bonded peer disconnecting is first advertised to directly, if it disconnected less than this
+    /// long ago. If `None`, directed advertising is not used.
+    ///
+    /// Peers are advertised to at their identity address, or at the address they connected from if
+    /// they didn't distribute one.
+    pub reconnect_window: Option<Duration>,
+    /// Delay before advertising again when no connection is available.
+    pub backoff: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            adv: peripheral::Config::default(),
+            fast_interval: 48, // 30ms
+            fast_duration: Duration::from_secs(30),
+            slow_interval: 1636, // 1022.5ms
+            reconnect_window: Some(Duration::from_secs(10)),
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Accepts up to `N` concurrent connections from connectable advertising.
+///
+/// Advertising only runs while [`accept`](Self::accept) is being awaited, it can't be used together
+/// with the other advertising functions.
+///
+/// The manager doesn't keep a reference to the connections it returns: as usual, a connection is
+/// disconnected once all its [`Connection`] instances are dropped.
+pub struct PeripheralManager<'a, const N: usize> {
+    sd: &'a Softdevice,
+    adv: ConnectableAdvertisement<'a>,
+    config: Config,
+    #[cfg(feature = "ble-sec")]
+    security_handler: Option<&'static dyn crate::ble::security::SecurityHandler>,
+    links: heapless::Vec<WeakConnection, N>,
+    fast_until: Instant,
+    reconnect: Option<(Address, Instant)>,
+}
+
+impl<'a, const N: usize> PeripheralManager<'a, N> {
+    /// Advertise `adv`, which should be an undirected advertisement.
+    pub fn new(sd: &'a Softdevice, adv: ConnectableAdvertisement<'a>, config: Config) -> Self {
+        Self {
+            sd,
+            adv,
+            config,
+            #[cfg(feature = "ble-sec")]
+            security_handler: None,
+            links: heapless::Vec::new(),
+            fast_until: Instant::now() + config.fast_duration,
+            reconnect: None,
+        }
+    }
+
+    /// Like [`new`](Self::new), with connections using `security_handler`, see
+    /// [`advertise_pairable`](peripheral::advertise_pairable).
+    #[cfg(feature = "ble-sec")]
+    pub fn pairable(
+        sd: &'a Softdevice,
+        adv: ConnectableAdvertisement<'a>,
+        config: Config,
+        security_handler: &'static dyn crate::ble::security::SecurityHandler,
+    ) -> Self {
+        let mut this = Self::new(sd, adv, config);
+        this.security_handler = Some(security_handler);
+        this
+    }
+
+    /// Number of connections accepted that are still connected.
+    pub fn links(&self) -> usize {
+        self.links
+            .iter()
+            .filter_map(WeakConnection::upgrade)
+            .filter(|conn| conn.disconnect_reason().is_none())
+            .count()
+    }
+
+    /// Advertise until a new connection is made.
+    ///
+    /// While `N` connections are up, this waits for one of them to disconnect before advertising.
+    /// Returns [`AdvertiseError::NoFreeConn`] only if advertising can't start even though no
+    /// connections from this manager are up.
+    pub async fn accept(&mut self) -> Result<Connection, AdvertiseError> {
+        loop {
+            self.prune();
+            if self.links.is_full() {
+                wait_disconnected(&self.links).await;
+                continue;
+            }
+
+            let now = Instant::now();
+            let mut config = self.config.adv;
+            let adv = match self.reconnect.take() {
+                Some((peer, deadline)) if now < deadline => {
+                    debug!("peripheral manager: directed advertising to {:?}", peer);
+                    config.timeout = Some(raw::BLE_GAP_ADV_TIMEOUT_HIGH_DUTY_MAX as u16);
+                    ConnectableAdvertisement::NonscannableDirectedHighDuty { peer }
+                }
+                _ if now < self.fast_until => {
+                    let remaining = (self.fast_until - now).as_millis() / 10;
+                    config.interval = self.config.fast_interval;
+                    config.timeout = Some(remaining.clamp(1, u16::MAX.into()) as u16);
+                    self.adv
+                }
+                _ => {
+                    config.interval = self.config.slow_interval;
+                    config.timeout = None;
+                    self.adv
+                }
+            };
+
+            // A link going down changes the phase, so stop advertising to pick the new one.
+            let res = match select(self.advertise(adv, &config), wait_disconnected(&self.links)).await {
+                Either::First(res) => res,
+                Either::Second(()) => continue,
+            };
+
+            match res {
+                Ok(conn) => {
+                    unwrap!(self.links.push(conn.downgrade()).ok());
+                    return Ok(conn);
+                }
+                // End of the phase.
+                Err(AdvertiseError::Timeout) => {}
+                Err(AdvertiseError::NoFreeConn | AdvertiseError::Raw(RawError::ConnCount)) => {
+                    if self.links.is_empty() {
+                        return Err(AdvertiseError::NoFreeConn);
+                    }
+                    debug!("peripheral manager: no free connection, backing off");
+                    select(Timer::after(self.config.backoff), wait_disconnected(&self.links)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn advertise(
+        &self,
+        adv: ConnectableAdvertisement<'_>,
+        config: &peripheral::Config,
+    ) -> Result<Connection, AdvertiseError> {
+        #[cfg(feature = "ble-sec")]
+        if let Some(security_handler) = self.security_handler {
+            return peripheral::advertise_pairable(self.sd, adv, config, security_handler).await;
+        }
+        peripheral::advertise_connectable(self.sd, adv, config).await
+    }
+
+    // Forget disconnected links, starting a new fast phase and remembering bonded peers to reconnect to.
+    fn prune(&mut self) {
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.links.len() {
+            // A connection that can't be upgraded anymore was disconnected and its slot reused, the
+            // reason and the peer are lost.
+            let conn = self.links[i].upgrade();
+            let reason = match &conn {
+                Some(conn) => match conn.disconnect_reason() {
+                    Some(reason) => Some(reason),
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            self.links.swap_remove(i);
+            debug!("peripheral manager: link disconnected: {:?}", reason);
+            self.fast_until = now + self.config.fast_duration;
+
+            // Don't chase peers we disconnected on purpose.
+            if let (Some(window), Some(conn), Some(reason)) = (self.config.reconnect_window, conn, reason) {
+                if reason != HciStatus::LOCAL_HOST_TERMINATED_CONNECTION {
+                    if let Some(peer) = conn.bonded_peer() {
+                        self.reconnect = Some((peer, now + window));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Wait until any of `links` is disconnected. Never returns if there are none.
+async fn wait_disconnected<const N: usize>(links: &heapless::Vec<WeakConnection, N>) {
+    let mut waits: heapless::Vec<_, N> = links.iter().map(WeakConnection::disconnected).collect();
+    poll_fn(|cx| {
+        for wait in &mut waits {
+            if Pin::new(wait).poll(cx).is_ready() {
+                return Poll::Ready(());
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
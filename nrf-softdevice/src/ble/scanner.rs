@@ -0,0 +1,475 @@
+//! Scanning as an async stream.
+//!
+//! Unlike [`central::scan`](crate::ble::central::scan), a [`Scanner`] keeps scanning between calls to
+//! [`Scanner::next`], and yields owned [`AdvertisementReport`]s. Reports are filtered and deduplicated
+//! as they arrive, so uninteresting or repeated advertisements never wake the scanning task.
+//!
+//! For active scanning, [`start_discovery`] additionally merges each advertisement with its scan
+//! response into a single [`DiscoveredDevice`].
+
+use core::cell::RefCell;
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant, Timer};
+use futures::future::poll_fn;
+use futures::Stream;
+
+use crate::ble::advertisement_parser::{AdStructure, AdStructures, ServiceUuid};
+use crate::ble::central::{report_data, scan_buf, AdvertisementReport, ScanConfig, ScanError};
+use crate::ble::Address;
+use crate::{raw, RawError, Softdevice};
+
+/// Number of received reports buffered until [`Scanner::next`] is called. Further reports are dropped.
+pub const QUEUE_LEN: usize = 4;
+
+/// Number of addresses remembered for deduplication. When full, the least recently reported
+/// address is forgotten.
+pub const DEDUP_TABLE_LEN: usize = 128;
+
+/// Filter applied to received reports. Only reports matching all the set conditions are yielded.
+#[derive(Clone, Copy, Default)]
+pub struct Filter {
+    /// Lists this service UUID, complete or incomplete list.
+    pub service_uuid: Option<ServiceUuid>,
+    /// Has a local name starting with this, complete or shortened name.
+    pub name_prefix: Option<&'static str>,
+    /// Has manufacturer specific data with this company id.
+    pub company_id: Option<u16>,
+    /// Received with at least this RSSI, in dBm.
+    pub min_rssi: Option<i8>,
+    /// Sent by one of these addresses.
+    ///
+    /// Unlike [`ScanConfig::whitelist`], this is applied in software and works with
+    /// any number of addresses.
+    pub addresses: Option<&'static [Address]>,
+}
+
+impl Filter {
+    fn matches(&self, report: &raw::ble_gap_evt_adv_report_t) -> bool {
+        if let Some(min_rssi) = self.min_rssi {
+            if report.rssi < min_rssi {
+                return false;
+            }
+        }
+
+        if let Some(addresses) = self.addresses {
+            let address = Address::from_raw(report.peer_addr);
+            if !addresses.contains(&address) {
+                return false;
+            }
+        }
+
+        // Safety: reports come from softdevice events.
+        self.matches_data(AdStructures::new(unsafe { report_data(report) }))
+    }
+
+    // Check the conditions on the advertising data, on all of `ads` taken together.
+    fn matches_data<'d>(&self, ads: impl Iterator<Item = AdStructure<'d>>) -> bool {
+        if self.service_uuid.is_none() && self.name_prefix.is_none() && self.company_id.is_none() {
+            return true;
+        }
+
+        let mut service_uuid = self.service_uuid.is_none();
+        let mut name_prefix = self.name_prefix.is_none();
+        let mut company_id = self.company_id.is_none();
+        for ad in ads {
+            match ad {
+                AdStructure::LocalName { name, .. } => {
+                    name_prefix |= self.name_prefix.is_some_and(|prefix| name.starts_with(prefix));
+                }
+                AdStructure::ManufacturerData { company_id: id, .. } => {
+                    company_id |= self.company_id == Some(id);
+                }
+                _ => {
+                    service_uuid |= self.service_uuid.is_some_and(|uuid| ad.lists_service(&uuid));
+                }
+            }
+        }
+
+        service_uuid && name_prefix && company_id
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Config<'a> {
+    pub scan: ScanConfig<'a>,
+    pub filter: Filter,
+    /// Yield reports from the same address at most once per this window.
+    ///
+    /// Advertisements and scan responses are deduplicated separately. If `None`, every
+    /// report is yielded.
+    pub dedup_window: Option<Duration>,
+}
+
+struct Seen {
+    address: Address,
+    scan_response: bool,
+    at: Instant,
+}
+
+struct State {
+    filter: Filter,
+    dedup_window: Option<Duration>,
+    seen: heapless::Vec<Seen, DEDUP_TABLE_LEN>,
+}
+
+impl State {
+    fn on_report(&mut self, report: &raw::ble_gap_evt_adv_report_t) {
+        if !self.filter.matches(report) {
+            return;
+        }
+
+        let now = Instant::now();
+        let address = Address::from_raw(report.peer_addr);
+        let scan_response = report.type_.scan_response() != 0;
+
+        let seen = match self.dedup_window {
+            None => None,
+            Some(window) => {
+                let seen = self
+                    .seen
+                    .iter()
+                    .position(|s| s.address == address && s.scan_response == scan_response);
+                if let Some(i) = seen {
+                    if now.saturating_duration_since(self.seen[i].at) < window {
+                        return;
+                    }
+                }
+                Some(seen)
+            }
+        };
+
+        // Safety: reports come from softdevice events.
+        let report = unsafe { AdvertisementReport::from_raw(report) };
+        if QUEUE.try_send(Ok(report)).is_err() {
+            // Not recorded as seen, so it is reported again next time.
+            return;
+        }
+
+        match seen {
+            None => {}
+            Some(Some(i)) => self.seen[i].at = now,
+            Some(None) => {
+                let entry = Seen {
+                    address,
+                    scan_response,
+                    at: now,
+                };
+                if let Err(entry) = self.seen.push(entry) {
+                    let oldest = self.seen.iter().enumerate().min_by_key(|(_, s)| s.at).map(|(i, _)| i);
+                    self.seen[unwrap!(oldest)] = entry;
+                }
+            }
+        }
+    }
+}
+
+static STATE: Mutex<CriticalSectionRawMutex, RefCell<Option<State>>> = Mutex::new(RefCell::new(None));
+static QUEUE: Channel<CriticalSectionRawMutex, Result<AdvertisementReport, ScanError>, QUEUE_LEN> = Channel::new();
+
+fn end(err: ScanError) {
+    // Make room for the error, losing a report is better than never ending.
+    if QUEUE.is_full() {
+        let _ = QUEUE.try_receive();
+    }
+    let _ = QUEUE.try_send(Err(err));
+}
+
+/// Handle an advertising report, returns false if no [`Scanner`] is running.
+pub(crate) fn on_adv_report(report: &raw::ble_gap_evt_adv_report_t) -> bool {
+    let running = STATE.lock(|state| match state.borrow_mut().as_mut() {
+        Some(state) => {
+            state.on_report(report);
+            true
+        }
+        None => false,
+    });
+    if !running {
+        return false;
+    }
+
+    // Resume scan
+    let ret = unsafe { raw::sd_ble_gap_scan_start(ptr::null(), scan_buf()) };
+    match RawError::convert(ret) {
+        Ok(()) => {}
+        // "The scanner has timed out when this function is called to continue scanning"
+        Err(RawError::InvalidState) => end(ScanError::Timeout),
+        Err(err) => {
+            warn!("sd_ble_gap_scan_start resume err {:?}", err);
+            end(ScanError::Raw(err));
+        }
+    }
+    true
+}
+
+/// Handle a scan timeout, returns false if no [`Scanner`] is running.
+pub(crate) fn on_timeout() -> bool {
+    let running = STATE.lock(|state| state.borrow().is_some());
+    if running {
+        end(ScanError::Timeout);
+    }
+    running
+}
+
+/// A running scan, see [`start`]. Scanning is stopped on drop.
+pub struct Scanner {
+    ended: Option<ScanError>,
+}
+
+/// Start scanning.
+///
+/// Only one scan can run at a time, this includes [`central::scan`](crate::ble::central::scan).
+pub fn start(sd: &Softdevice, config: &Config<'_>) -> Result<Scanner, ScanError> {
+    let _ = sd;
+    if !config.scan.phys_valid() {
+        return Err(ScanError::InvalidPhy);
+    }
+    let scan_params = config.scan.to_raw()?;
+
+    let running = STATE.lock(|state| {
+        let mut state = state.borrow_mut();
+        if state.is_some() {
+            return true;
+        }
+        *state = Some(State {
+            filter: config.filter,
+            dedup_window: config.dedup_window,
+            seen: heapless::Vec::new(),
+        });
+        false
+    });
+    if running {
+        warn!("scanner already running");
+        return Err(ScanError::Raw(RawError::InvalidState));
+    }
+    QUEUE.clear();
+
+    let ret = unsafe { raw::sd_ble_gap_scan_start(&scan_params, scan_buf()) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_ble_gap_scan_start err {:?}", err);
+        STATE.lock(|state| *state.borrow_mut() = None);
+        return Err(ScanError::Raw(err));
+    }
+
+    debug!("Scanner started");
+    Ok(Scanner { ended: None })
+}
+
+impl Scanner {
+    /// Wait for the next report passing the filters.
+    ///
+    /// Once scanning has ended, for example because of [`ScanConfig::timeout`], this keeps
+    /// returning the error that ended it.
+    pub async fn next(&mut self) -> Result<AdvertisementReport, ScanError> {
+        if let Some(err) = self.ended {
+            return Err(err);
+        }
+        let res = poll_fn(|cx| QUEUE.poll_receive(cx)).await;
+        if let Err(err) = res {
+            self.ended = Some(err);
+        }
+        res
+    }
+}
+
+impl Stream for Scanner {
+    type Item = Result<AdvertisementReport, ScanError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ended.is_some() {
+            return Poll::Ready(None);
+        }
+        QUEUE.poll_receive(cx).map(|res| {
+            if let Err(err) = res {
+                self.ended = Some(err);
+            }
+            Some(res)
+        })
+    }
+}
+
+impl Drop for Scanner {
+    fn drop(&mut self) {
+        let ret = unsafe { raw::sd_ble_gap_scan_stop() };
+        match RawError::convert(ret) {
+            Ok(_) => {}
+            Err(RawError::InvalidState) => {} // scan stopped itself due to timeout, erroring is normal.
+            Err(_e) => warn!("sd_ble_gap_scan_stop: {:?}", _e),
+        }
+        STATE.lock(|state| *state.borrow_mut() = None);
+        QUEUE.clear();
+    }
+}
+
+/// A device found by active scanning, see [`start_discovery`].
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    advertisement: Option<AdvertisementReport>,
+    scan_response: Option<AdvertisementReport>,
+}
+
+impl DiscoveredDevice {
+    pub fn address(&self) -> Address {
+        self.latest().address()
+    }
+
+    /// RSSI of the latest received report, in dBm.
+    pub fn rssi(&self) -> i8 {
+        self.latest().rssi()
+    }
+
+    /// The advertisement. This is `None` if only a scan response was received, which happens when
+    /// the advertisement was filtered out or dropped.
+    pub fn advertisement(&self) -> Option<&AdvertisementReport> {
+        self.advertisement.as_ref()
+    }
+
+    /// The scan response. This is `None` if the advertiser isn't scannable, or didn't respond in time.
+    pub fn scan_response(&self) -> Option<&AdvertisementReport> {
+        self.scan_response.as_ref()
+    }
+
+    /// Iterate over the AD structures of the advertisement, then those of the scan response.
+    pub fn ad_structures(&self) -> impl Iterator<Item = AdStructure<'_>> {
+        let advertisement = self.advertisement.iter().flat_map(|r| r.ad_structures());
+        let scan_response = self.scan_response.iter().flat_map(|r| r.ad_structures());
+        advertisement.chain(scan_response)
+    }
+
+    fn latest(&self) -> &AdvertisementReport {
+        unwrap!(self.scan_response.as_ref().or(self.advertisement.as_ref()))
+    }
+}
+
+struct Pending {
+    advertisement: AdvertisementReport,
+    deadline: Instant,
+}
+
+/// A running active scan merging advertisements with their scan response, see [`start_discovery`].
+///
+/// Up to `N` advertisements are held while waiting for their scan response.
+pub struct Discovery<const N: usize> {
+    scanner: Scanner,
+    filter: Filter,
+    scan_response_timeout: Duration,
+    pending: heapless::Vec<Pending, N>,
+}
+
+/// Start an active scan, yielding each advertisement together with its scan response.
+///
+/// `config.scan.active` is forced to true. Advertisements from scannable advertisers are held
+/// until the scan response from the same address arrives, or for at most `scan_response_timeout`.
+///
+/// `config.filter` applies to the advertisement and the scan response taken together, for
+/// example a device listing a service in its advertisement and its name in the scan response
+/// matches a filter on both. `min_rssi` and `addresses` still apply to each report.
+pub fn start_discovery<const N: usize>(
+    sd: &Softdevice,
+    config: &Config<'_>,
+    scan_response_timeout: Duration,
+) -> Result<Discovery<N>, ScanError> {
+    let mut config = *config;
+    config.scan.active = true;
+    let filter = config.filter;
+    config.filter = Filter {
+        service_uuid: None,
+        name_prefix: None,
+        company_id: None,
+        ..filter
+    };
+    Ok(Discovery {
+        scanner: start(sd, &config)?,
+        filter,
+        scan_response_timeout,
+        pending: heapless::Vec::new(),
+    })
+}
+
+impl<const N: usize> Discovery<N> {
+    /// Wait for the next discovered device.
+    ///
+    /// Once scanning has ended, the held advertisements passing the filter are returned first, then
+    /// this keeps returning the error that ended it.
+    pub async fn next(&mut self) -> Result<DiscoveredDevice, ScanError> {
+        loop {
+            let device = self.next_merged().await?;
+            if self.filter.matches_data(device.ad_structures()) {
+                return Ok(device);
+            }
+        }
+    }
+
+    async fn next_merged(&mut self) -> Result<DiscoveredDevice, ScanError> {
+        loop {
+            let now = Instant::now();
+            if let Some(i) = self.pending.iter().position(|p| p.deadline <= now) {
+                return Ok(self.flush(i));
+            }
+
+            let report = match self.pending.iter().map(|p| p.deadline).min() {
+                Some(deadline) => match select(self.scanner.next(), Timer::at(deadline)).await {
+                    Either::First(res) => res,
+                    Either::Second(()) => continue,
+                },
+                None => self.scanner.next().await,
+            };
+
+            let report = match report {
+                Ok(report) => report,
+                Err(err) if self.pending.is_empty() => return Err(err),
+                Err(_) => return Ok(self.flush(0)),
+            };
+
+            let address = report.address();
+            let pending = self.pending.iter().position(|p| p.advertisement.address() == address);
+
+            if report.is_scan_response() {
+                let advertisement = pending.map(|i| self.pending.swap_remove(i).advertisement);
+                return Ok(DiscoveredDevice {
+                    advertisement,
+                    scan_response: Some(report),
+                });
+            }
+
+            if !report.is_scannable() {
+                return Ok(DiscoveredDevice {
+                    advertisement: Some(report),
+                    scan_response: None,
+                });
+            }
+
+            let entry = Pending {
+                advertisement: report,
+                deadline: Instant::now() + self.scan_response_timeout,
+            };
+            match pending {
+                // Repeated advertisement, keep the latest one without extending the deadline.
+                Some(i) => self.pending[i].advertisement = entry.advertisement,
+                None => {
+                    if let Err(entry) = self.pending.push(entry) {
+                        // Table full, give up on the oldest one to make room.
+                        let oldest = self.pending.iter().enumerate().min_by_key(|(_, p)| p.deadline);
+                        let oldest = unwrap!(oldest.map(|(i, _)| i));
+                        let device = self.flush(oldest);
+                        unwrap!(self.pending.push(entry).ok());
+                        return Ok(device);
+                    }
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self, i: usize) -> DiscoveredDevice {
+        DiscoveredDevice {
+            advertisement: Some(self.pending.swap_remove(i).advertisement),
+            scan_response: None,
+        }
+    }
+}
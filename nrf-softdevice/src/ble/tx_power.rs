@@ -0,0 +1,114 @@
+//! Adaptive transmit power control for connections.
+//!
+//! Lowering the transmit power when the peer is close saves energy, so [`run`] steps the
+//! connection's [`TxPower`] up or down to keep the measured RSSI inside a target window.
+//! The link is assumed to be roughly symmetric: the RSSI measured here is taken as an
+//! estimate of how well the peer receives us.
+
+use crate::ble::{Connection, DisconnectedError, SetTxPowerError, TxPower};
+
+// All power levels, in increasing order.
+const LEVELS: &[TxPower] = &[
+    TxPower::Minus40dBm,
+    TxPower::Minus20dBm,
+    TxPower::Minus16dBm,
+    TxPower::Minus12dBm,
+    TxPower::Minus8dBm,
+    TxPower::Minus4dBm,
+    TxPower::ZerodBm,
+    #[cfg(feature = "s140")]
+    TxPower::Plus2dBm,
+    TxPower::Plus3dBm,
+    TxPower::Plus4dBm,
+    #[cfg(feature = "s140")]
+    TxPower::Plus5dBm,
+    #[cfg(feature = "s140")]
+    TxPower::Plus6dBm,
+    #[cfg(feature = "s140")]
+    TxPower::Plus7dBm,
+    #[cfg(feature = "s140")]
+    TxPower::Plus8dBm,
+];
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Transmit power is raised while the RSSI is below this, in dBm.
+    pub min_rssi: i8,
+    /// Transmit power is lowered while the RSSI is above this, in dBm.
+    pub max_rssi: i8,
+    /// Lowest transmit power to use.
+    pub min_tx_power: TxPower,
+    /// Highest transmit power to use.
+    pub max_tx_power: TxPower,
+    /// Extra margin in dB the RSSI must go past a threshold by to step back in the opposite
+    /// direction of the last change, so noise around one threshold doesn't flip the power back and forth.
+    pub hysteresis: u8,
+    /// Number of RSSI reports ignored after each change, while the peer and the measurements adapt.
+    pub settle_reports: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_rssi: -70,
+            max_rssi: -50,
+            min_tx_power: TxPower::Minus20dBm,
+            max_tx_power: TxPower::Plus4dBm,
+            hysteresis: 4,
+            settle_reports: 2,
+        }
+    }
+}
+
+/// Adjust the transmit power of `conn` until it is disconnected.
+///
+/// The power is changed by one step when a reported RSSI is outside `min_rssi..=max_rssi`, then
+/// `settle_reports` reports are ignored. Going back in the opposite direction needs the RSSI to be
+/// `hysteresis` dB further out. RSSI measurement must be started with [`Connection::start_rssi`]
+/// or [`Connection::start_rssi_with`].
+pub async fn run(conn: &Connection, config: &Config) -> DisconnectedError {
+    let hysteresis = config.hysteresis as i8;
+    // Direction of the last change, 1 when raised and -1 when lowered.
+    let mut last_step = 0;
+    loop {
+        let min_rssi = if last_step < 0 {
+            config.min_rssi.saturating_sub(hysteresis)
+        } else {
+            config.min_rssi
+        };
+        let max_rssi = if last_step > 0 {
+            config.max_rssi.saturating_add(hysteresis)
+        } else {
+            config.max_rssi
+        };
+        let rssi = match conn.wait_rssi(|rssi| rssi < min_rssi || rssi > max_rssi).await {
+            Ok(rssi) => rssi,
+            Err(err) => return err,
+        };
+
+        let current = conn.tx_power();
+        let delta = if rssi < min_rssi { 1 } else { -1 };
+        let next = step(current, delta)
+            .filter(|&p| p as i8 <= config.max_tx_power as i8 && p as i8 >= config.min_tx_power as i8);
+
+        match next {
+            Some(next) => {
+                debug!("tx_power: rssi {:?}, changing tx power to {:?} dBm", rssi, next as i8);
+                if let Err(SetTxPowerError::Disconnected) = conn.set_tx_power(next) {
+                    return DisconnectedError;
+                }
+                last_step = delta;
+                for _ in 0..config.settle_reports {
+                    conn.wait_next_rssi().await;
+                }
+            }
+            // Already at the limit, wait for the RSSI to change.
+            None => conn.wait_next_rssi().await,
+        }
+    }
+}
+
+fn step(tx_power: TxPower, delta: isize) -> Option<TxPower> {
+    let index = LEVELS.iter().position(|&p| p == tx_power)?;
+    LEVELS.get(index.checked_add_signed(delta)?).copied()
+}
@@ -1,7 +1,7 @@
 use core::mem;
 use core::num::NonZeroU16;
 
-use crate::{raw, RawError};
+use crate::{random_bytes, raw, RandomError, RawError, Softdevice};
 
 #[repr(transparent)]
 #[derive(Copy, Clone)]
@@ -261,7 +261,7 @@ pub enum TxPower {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum Phy {
     /// 1Mbps phy
@@ -269,10 +269,218 @@ pub enum Phy {
     /// 2Mbps phy
     M2 = 2,
     /// Coded phy (125kbps, S=8)
+    ///
+    /// The softdevice always transmits with S=8 coding, and receives both S=2 and S=8. The coding
+    /// scheme can't be selected.
     #[cfg(feature = "s140")]
     Coded = 4,
 }
 
+impl Phy {
+    pub(crate) fn from_raw(phy: u8) -> Self {
+        match u32::from(phy) {
+            raw::BLE_GAP_PHY_2MBPS => Phy::M2,
+            #[cfg(feature = "s140")]
+            raw::BLE_GAP_PHY_CODED => Phy::Coded,
+            _ => Phy::M1,
+        }
+    }
+}
+
+/// Connection parameters in physical units.
+///
+/// The softdevice uses 1.25ms units for connection intervals and 10ms units for the supervision
+/// timeout, see [`to_raw`](Self::to_raw) and [`from_raw`](Self::from_raw).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnParams {
+    /// Minimum connection interval, in microseconds.
+    pub min_interval_us: u32,
+    /// Maximum connection interval, in microseconds.
+    pub max_interval_us: u32,
+    /// Number of connection events the peripheral may skip.
+    pub latency: u16,
+    /// Supervision timeout, in milliseconds.
+    pub supervision_timeout_ms: u32,
+}
+
+/// Rule violated by [`ConnParams`], see [`ConnParams::validate`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InvalidConnParams {
+    /// A connection interval is outside 7.5ms..=4s.
+    IntervalOutOfRange,
+    /// The minimum connection interval is above the maximum.
+    IntervalOrder,
+    /// The latency is above 499.
+    LatencyOutOfRange,
+    /// The supervision timeout is outside 100ms..=32s.
+    TimeoutOutOfRange,
+    /// The supervision timeout is not above `(1 + latency) * max_interval * 2`.
+    TimeoutTooShort,
+}
+
+impl ConnParams {
+    /// Long intervals with some latency, for devices that rarely exchange data.
+    pub const LOW_POWER: Self = Self {
+        min_interval_us: 400_000,
+        max_interval_us: 650_000,
+        latency: 2,
+        supervision_timeout_ms: 6_000,
+    };
+
+    /// Same as the default `ConnectConfig::conn_params`.
+    pub const BALANCED: Self = Self {
+        min_interval_us: 50_000,
+        max_interval_us: 250_000,
+        latency: 0,
+        supervision_timeout_ms: 4_000,
+    };
+
+    /// Shortest intervals, for bulk data transfer.
+    pub const HIGH_THROUGHPUT: Self = Self {
+        min_interval_us: 7_500,
+        max_interval_us: 15_000,
+        latency: 0,
+        supervision_timeout_ms: 4_000,
+    };
+
+    /// Check the parameters against the rules of the Bluetooth specification.
+    ///
+    /// This checks the values after rounding to softdevice units, as done by [`to_raw`](Self::to_raw).
+    pub fn validate(&self) -> Result<(), InvalidConnParams> {
+        validate_raw(&self.round())
+    }
+
+    /// Convert to softdevice units, rounding to the nearest unit.
+    pub fn to_raw(&self) -> Result<raw::ble_gap_conn_params_t, InvalidConnParams> {
+        let params = self.round();
+        validate_raw(&params)?;
+        Ok(params)
+    }
+
+    /// Create parameters from durations, saturating at `u32::MAX` units.
+    ///
+    /// They're not validated until [`validate`](Self::validate) or [`to_raw`](Self::to_raw).
+    #[cfg(feature = "embassy-time")]
+    pub fn new(
+        min_interval: embassy_time::Duration,
+        max_interval: embassy_time::Duration,
+        latency: u16,
+        supervision_timeout: embassy_time::Duration,
+    ) -> Self {
+        let saturate = |value: u64| value.min(u32::MAX.into()) as u32;
+        Self {
+            min_interval_us: saturate(min_interval.as_micros()),
+            max_interval_us: saturate(max_interval.as_micros()),
+            latency,
+            supervision_timeout_ms: saturate(supervision_timeout.as_millis()),
+        }
+    }
+
+    /// Convert from softdevice units. This is exact, as the physical units are finer.
+    pub fn from_raw(params: &raw::ble_gap_conn_params_t) -> Self {
+        Self {
+            min_interval_us: u32::from(params.min_conn_interval) * 1250,
+            max_interval_us: u32::from(params.max_conn_interval) * 1250,
+            latency: params.slave_latency,
+            supervision_timeout_ms: u32::from(params.conn_sup_timeout) * 10,
+        }
+    }
+
+    fn round(&self) -> raw::ble_gap_conn_params_t {
+        fn units(value: u32, unit: u32) -> u16 {
+            (value.saturating_add(unit / 2) / unit).min(u16::MAX as u32) as u16
+        }
+
+        raw::ble_gap_conn_params_t {
+            min_conn_interval: units(self.min_interval_us, 1250),
+            max_conn_interval: units(self.max_interval_us, 1250),
+            slave_latency: self.latency,
+            conn_sup_timeout: units(self.supervision_timeout_ms, 10),
+        }
+    }
+}
+
+impl Default for ConnParams {
+    fn default() -> Self {
+        Self::BALANCED
+    }
+}
+
+fn validate_raw(params: &raw::ble_gap_conn_params_t) -> Result<(), InvalidConnParams> {
+    let interval_range = raw::BLE_GAP_CP_MIN_CONN_INTVL_MIN..=raw::BLE_GAP_CP_MAX_CONN_INTVL_MAX;
+    if !interval_range.contains(&u32::from(params.min_conn_interval))
+        || !interval_range.contains(&u32::from(params.max_conn_interval))
+    {
+        return Err(InvalidConnParams::IntervalOutOfRange);
+    }
+    if params.min_conn_interval > params.max_conn_interval {
+        return Err(InvalidConnParams::IntervalOrder);
+    }
+    if u32::from(params.slave_latency) > raw::BLE_GAP_CP_SLAVE_LATENCY_MAX {
+        return Err(InvalidConnParams::LatencyOutOfRange);
+    }
+    let timeout_range = raw::BLE_GAP_CP_CONN_SUP_TIMEOUT_MIN..=raw::BLE_GAP_CP_CONN_SUP_TIMEOUT_MAX;
+    if !timeout_range.contains(&u32::from(params.conn_sup_timeout)) {
+        return Err(InvalidConnParams::TimeoutOutOfRange);
+    }
+
+    // In microseconds, to compare 1.25ms and 10ms units.
+    let timeout_us = u32::from(params.conn_sup_timeout) * 10_000;
+    let min_timeout_us = (1 + u32::from(params.slave_latency)) * u32::from(params.max_conn_interval) * 1250 * 2;
+    if timeout_us <= min_timeout_us {
+        return Err(InvalidConnParams::TimeoutTooShort);
+    }
+    Ok(())
+}
+
+/// Set of enabled BLE data channels, indexed by channel index (0..=36).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelMap([u8; 5]);
+
+impl ChannelMap {
+    /// Number of data channels.
+    pub const CHANNEL_COUNT: u8 = 37;
+
+    /// All data channels enabled.
+    pub const ALL: Self = Self([0xff, 0xff, 0xff, 0xff, 0x1f]);
+    /// No data channels enabled.
+    pub const NONE: Self = Self([0; 5]);
+
+    /// Create a channel map from its 37-bit raw representation, LSB first.
+    ///
+    /// Bits for the advertising channels (37-39) are ignored.
+    pub const fn from_raw(raw: [u8; 5]) -> Self {
+        Self([raw[0], raw[1], raw[2], raw[3], raw[4] & 0x1f])
+    }
+
+    pub const fn to_raw(self) -> [u8; 5] {
+        self.0
+    }
+
+    pub const fn is_enabled(&self, channel: u8) -> bool {
+        core::assert!(channel < Self::CHANNEL_COUNT, "invalid data channel index");
+        self.0[channel as usize / 8] & (1 << (channel % 8)) != 0
+    }
+
+    pub fn set_enabled(&mut self, channel: u8, enabled: bool) {
+        assert!(channel < Self::CHANNEL_COUNT, "invalid data channel index");
+        let bit = 1 << (channel % 8);
+        if enabled {
+            self.0[channel as usize / 8] |= bit;
+        } else {
+            self.0[channel as usize / 8] &= !bit;
+        }
+    }
+
+    /// Number of enabled channels.
+    pub fn count(&self) -> u8 {
+        self.0.iter().map(|b| b.count_ones() as u8).sum()
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
@@ -297,6 +505,14 @@ pub enum PhySet {
     M1M2Coded = 7,
 }
 
+impl PhySet {
+    /// Whether the set contains the coded phy.
+    #[cfg(feature = "s140")]
+    pub const fn has_coded(self) -> bool {
+        self as u8 & raw::BLE_GAP_PHY_CODED as u8 != 0
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -352,6 +568,21 @@ pub struct IdentityResolutionKey {
 }
 
 impl IdentityResolutionKey {
+    pub const fn new(irk: [u8; 16]) -> Self {
+        Self { irk }
+    }
+
+    /// Generate a new random IRK using the softdevice's random number generator.
+    pub fn generate(sd: &Softdevice) -> Result<Self, RandomError> {
+        let mut irk = [0; 16];
+        random_bytes(sd, &mut irk)?;
+        Ok(Self { irk })
+    }
+
+    pub fn bytes(&self) -> [u8; 16] {
+        self.irk
+    }
+
     pub fn from_raw(raw: raw::ble_gap_irk_t) -> Self {
         Self { irk: raw.irk }
     }
@@ -426,6 +657,371 @@ fn random_address_hash(key: IdentityResolutionKey, r: [u8; 3]) -> [u8; 3] {
     res
 }
 
+/// External appearance of the device, as defined in the Bluetooth SIG assigned numbers.
+///
+/// The value is made of a 10-bit category and a 6-bit subcategory. Subcategory 0 is the generic
+/// device of each category.
+///
+/// The associated constants list all the categories and subcategories of the assigned numbers
+/// (Assigned Numbers, section 2.6). Values assigned later can be built with [`Appearance::new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Appearance(u16);
+
+impl Appearance {
+    pub const UNKNOWN: Appearance = Appearance(0x0000);
+    pub const GENERIC_PHONE: Appearance = Appearance(0x0040);
+    pub const GENERIC_COMPUTER: Appearance = Appearance(0x0080);
+    pub const COMPUTER_DESKTOP_WORKSTATION: Appearance = Appearance(0x0081);
+    pub const COMPUTER_SERVER_CLASS_COMPUTER: Appearance = Appearance(0x0082);
+    pub const COMPUTER_LAPTOP: Appearance = Appearance(0x0083);
+    pub const COMPUTER_HANDHELD_PC_PDA: Appearance = Appearance(0x0084);
+    pub const COMPUTER_PALM_SIZE_PC_PDA: Appearance = Appearance(0x0085);
+    pub const COMPUTER_WEARABLE_COMPUTER: Appearance = Appearance(0x0086);
+    pub const COMPUTER_TABLET: Appearance = Appearance(0x0087);
+    pub const COMPUTER_DOCKING_STATION: Appearance = Appearance(0x0088);
+    pub const COMPUTER_ALL_IN_ONE: Appearance = Appearance(0x0089);
+    pub const COMPUTER_BLADE_SERVER: Appearance = Appearance(0x008A);
+    pub const COMPUTER_CONVERTIBLE: Appearance = Appearance(0x008B);
+    pub const COMPUTER_DETACHABLE: Appearance = Appearance(0x008C);
+    pub const COMPUTER_IOT_GATEWAY: Appearance = Appearance(0x008D);
+    pub const COMPUTER_MINI_PC: Appearance = Appearance(0x008E);
+    pub const COMPUTER_STICK_PC: Appearance = Appearance(0x008F);
+    pub const GENERIC_WATCH: Appearance = Appearance(0x00C0);
+    pub const WATCH_SPORTS_WATCH: Appearance = Appearance(0x00C1);
+    pub const WATCH_SMARTWATCH: Appearance = Appearance(0x00C2);
+    pub const GENERIC_CLOCK: Appearance = Appearance(0x0100);
+    pub const GENERIC_DISPLAY: Appearance = Appearance(0x0140);
+    pub const GENERIC_REMOTE_CONTROL: Appearance = Appearance(0x0180);
+    pub const GENERIC_EYE_GLASSES: Appearance = Appearance(0x01C0);
+    pub const GENERIC_TAG: Appearance = Appearance(0x0200);
+    pub const GENERIC_KEYRING: Appearance = Appearance(0x0240);
+    pub const GENERIC_MEDIA_PLAYER: Appearance = Appearance(0x0280);
+    pub const GENERIC_BARCODE_SCANNER: Appearance = Appearance(0x02C0);
+    pub const GENERIC_THERMOMETER: Appearance = Appearance(0x0300);
+    pub const THERMOMETER_EAR: Appearance = Appearance(0x0301);
+    pub const GENERIC_HEART_RATE_SENSOR: Appearance = Appearance(0x0340);
+    pub const HEART_RATE_SENSOR_HEART_RATE_BELT: Appearance = Appearance(0x0341);
+    pub const GENERIC_BLOOD_PRESSURE: Appearance = Appearance(0x0380);
+    pub const BLOOD_PRESSURE_ARM: Appearance = Appearance(0x0381);
+    pub const BLOOD_PRESSURE_WRIST: Appearance = Appearance(0x0382);
+    pub const GENERIC_HID: Appearance = Appearance(0x03C0);
+    pub const HID_KEYBOARD: Appearance = Appearance(0x03C1);
+    pub const HID_MOUSE: Appearance = Appearance(0x03C2);
+    pub const HID_JOYSTICK: Appearance = Appearance(0x03C3);
+    pub const HID_GAMEPAD: Appearance = Appearance(0x03C4);
+    pub const HID_DIGITIZER_TABLET: Appearance = Appearance(0x03C5);
+    pub const HID_CARD_READER: Appearance = Appearance(0x03C6);
+    pub const HID_DIGITAL_PEN: Appearance = Appearance(0x03C7);
+    pub const HID_BARCODE_SCANNER: Appearance = Appearance(0x03C8);
+    pub const HID_TOUCHPAD: Appearance = Appearance(0x03C9);
+    pub const HID_PRESENTATION_REMOTE: Appearance = Appearance(0x03CA);
+    pub const GENERIC_GLUCOSE_METER: Appearance = Appearance(0x0400);
+    pub const GENERIC_RUNNING_WALKING_SENSOR: Appearance = Appearance(0x0440);
+    pub const RUNNING_WALKING_SENSOR_IN_SHOE: Appearance = Appearance(0x0441);
+    pub const RUNNING_WALKING_SENSOR_ON_SHOE: Appearance = Appearance(0x0442);
+    pub const RUNNING_WALKING_SENSOR_ON_HIP: Appearance = Appearance(0x0443);
+    pub const GENERIC_CYCLING: Appearance = Appearance(0x0480);
+    pub const CYCLING_CYCLING_COMPUTER: Appearance = Appearance(0x0481);
+    pub const CYCLING_SPEED_SENSOR: Appearance = Appearance(0x0482);
+    pub const CYCLING_CADENCE_SENSOR: Appearance = Appearance(0x0483);
+    pub const CYCLING_POWER_SENSOR: Appearance = Appearance(0x0484);
+    pub const CYCLING_SPEED_AND_CADENCE_SENSOR: Appearance = Appearance(0x0485);
+    pub const GENERIC_CONTROL_DEVICE: Appearance = Appearance(0x04C0);
+    pub const CONTROL_DEVICE_SWITCH: Appearance = Appearance(0x04C1);
+    pub const CONTROL_DEVICE_MULTI_SWITCH: Appearance = Appearance(0x04C2);
+    pub const CONTROL_DEVICE_BUTTON: Appearance = Appearance(0x04C3);
+    pub const CONTROL_DEVICE_SLIDER: Appearance = Appearance(0x04C4);
+    pub const CONTROL_DEVICE_ROTARY_SWITCH: Appearance = Appearance(0x04C5);
+    pub const CONTROL_DEVICE_TOUCH_PANEL: Appearance = Appearance(0x04C6);
+    pub const CONTROL_DEVICE_SINGLE_SWITCH: Appearance = Appearance(0x04C7);
+    pub const CONTROL_DEVICE_DOUBLE_SWITCH: Appearance = Appearance(0x04C8);
+    pub const CONTROL_DEVICE_TRIPLE_SWITCH: Appearance = Appearance(0x04C9);
+    pub const CONTROL_DEVICE_BATTERY_SWITCH: Appearance = Appearance(0x04CA);
+    pub const CONTROL_DEVICE_ENERGY_HARVESTING_SWITCH: Appearance = Appearance(0x04CB);
+    pub const CONTROL_DEVICE_PUSH_BUTTON: Appearance = Appearance(0x04CC);
+    pub const CONTROL_DEVICE_DIAL: Appearance = Appearance(0x04CD);
+    pub const GENERIC_NETWORK_DEVICE: Appearance = Appearance(0x0500);
+    pub const NETWORK_DEVICE_ACCESS_POINT: Appearance = Appearance(0x0501);
+    pub const NETWORK_DEVICE_MESH_DEVICE: Appearance = Appearance(0x0502);
+    pub const NETWORK_DEVICE_MESH_NETWORK_PROXY: Appearance = Appearance(0x0503);
+    pub const GENERIC_SENSOR: Appearance = Appearance(0x0540);
+    pub const SENSOR_MOTION_SENSOR: Appearance = Appearance(0x0541);
+    pub const SENSOR_AIR_QUALITY_SENSOR: Appearance = Appearance(0x0542);
+    pub const SENSOR_TEMPERATURE_SENSOR: Appearance = Appearance(0x0543);
+    pub const SENSOR_HUMIDITY_SENSOR: Appearance = Appearance(0x0544);
+    pub const SENSOR_LEAK_SENSOR: Appearance = Appearance(0x0545);
+    pub const SENSOR_SMOKE_SENSOR: Appearance = Appearance(0x0546);
+    pub const SENSOR_OCCUPANCY_SENSOR: Appearance = Appearance(0x0547);
+    pub const SENSOR_CONTACT_SENSOR: Appearance = Appearance(0x0548);
+    pub const SENSOR_CARBON_MONOXIDE_SENSOR: Appearance = Appearance(0x0549);
+    pub const SENSOR_CARBON_DIOXIDE_SENSOR: Appearance = Appearance(0x054A);
+    pub const SENSOR_AMBIENT_LIGHT_SENSOR: Appearance = Appearance(0x054B);
+    pub const SENSOR_ENERGY_SENSOR: Appearance = Appearance(0x054C);
+    pub const SENSOR_COLOR_LIGHT_SENSOR: Appearance = Appearance(0x054D);
+    pub const SENSOR_RAIN_SENSOR: Appearance = Appearance(0x054E);
+    pub const SENSOR_FIRE_SENSOR: Appearance = Appearance(0x054F);
+    pub const SENSOR_WIND_SENSOR: Appearance = Appearance(0x0550);
+    pub const SENSOR_PROXIMITY_SENSOR: Appearance = Appearance(0x0551);
+    pub const SENSOR_MULTI_SENSOR: Appearance = Appearance(0x0552);
+    pub const SENSOR_FLUSH_MOUNTED_SENSOR: Appearance = Appearance(0x0553);
+    pub const SENSOR_CEILING_MOUNTED_SENSOR: Appearance = Appearance(0x0554);
+    pub const SENSOR_WALL_MOUNTED_SENSOR: Appearance = Appearance(0x0555);
+    pub const SENSOR_MULTISENSOR: Appearance = Appearance(0x0556);
+    pub const SENSOR_ENERGY_METER: Appearance = Appearance(0x0557);
+    pub const SENSOR_FLAME_DETECTOR: Appearance = Appearance(0x0558);
+    pub const SENSOR_VEHICLE_TIRE_PRESSURE_SENSOR: Appearance = Appearance(0x0559);
+    pub const GENERIC_LIGHT_FIXTURES: Appearance = Appearance(0x0580);
+    pub const LIGHT_FIXTURES_WALL_LIGHT: Appearance = Appearance(0x0581);
+    pub const LIGHT_FIXTURES_CEILING_LIGHT: Appearance = Appearance(0x0582);
+    pub const LIGHT_FIXTURES_FLOOR_LIGHT: Appearance = Appearance(0x0583);
+    pub const LIGHT_FIXTURES_CABINET_LIGHT: Appearance = Appearance(0x0584);
+    pub const LIGHT_FIXTURES_DESK_LIGHT: Appearance = Appearance(0x0585);
+    pub const LIGHT_FIXTURES_TROFFER_LIGHT: Appearance = Appearance(0x0586);
+    pub const LIGHT_FIXTURES_PENDANT_LIGHT: Appearance = Appearance(0x0587);
+    pub const LIGHT_FIXTURES_IN_GROUND_LIGHT: Appearance = Appearance(0x0588);
+    pub const LIGHT_FIXTURES_FLOOD_LIGHT: Appearance = Appearance(0x0589);
+    pub const LIGHT_FIXTURES_UNDERWATER_LIGHT: Appearance = Appearance(0x058A);
+    pub const LIGHT_FIXTURES_BOLLARD_WITH_LIGHT: Appearance = Appearance(0x058B);
+    pub const LIGHT_FIXTURES_PATHWAY_LIGHT: Appearance = Appearance(0x058C);
+    pub const LIGHT_FIXTURES_GARDEN_LIGHT: Appearance = Appearance(0x058D);
+    pub const LIGHT_FIXTURES_POLE_TOP_LIGHT: Appearance = Appearance(0x058E);
+    pub const LIGHT_FIXTURES_SPOTLIGHT: Appearance = Appearance(0x058F);
+    pub const LIGHT_FIXTURES_LINEAR_LIGHT: Appearance = Appearance(0x0590);
+    pub const LIGHT_FIXTURES_STREET_LIGHT: Appearance = Appearance(0x0591);
+    pub const LIGHT_FIXTURES_SHELVES_LIGHT: Appearance = Appearance(0x0592);
+    pub const LIGHT_FIXTURES_BAY_LIGHT: Appearance = Appearance(0x0593);
+    pub const LIGHT_FIXTURES_EMERGENCY_EXIT_LIGHT: Appearance = Appearance(0x0594);
+    pub const LIGHT_FIXTURES_LIGHT_CONTROLLER: Appearance = Appearance(0x0595);
+    pub const LIGHT_FIXTURES_LIGHT_DRIVER: Appearance = Appearance(0x0596);
+    pub const LIGHT_FIXTURES_BULB: Appearance = Appearance(0x0597);
+    pub const LIGHT_FIXTURES_LOW_BAY_LIGHT: Appearance = Appearance(0x0598);
+    pub const LIGHT_FIXTURES_HIGH_BAY_LIGHT: Appearance = Appearance(0x0599);
+    pub const GENERIC_FAN: Appearance = Appearance(0x05C0);
+    pub const FAN_CEILING_FAN: Appearance = Appearance(0x05C1);
+    pub const FAN_AXIAL_FAN: Appearance = Appearance(0x05C2);
+    pub const FAN_EXHAUST_FAN: Appearance = Appearance(0x05C3);
+    pub const FAN_PEDESTAL_FAN: Appearance = Appearance(0x05C4);
+    pub const FAN_DESK_FAN: Appearance = Appearance(0x05C5);
+    pub const FAN_WALL_FAN: Appearance = Appearance(0x05C6);
+    pub const GENERIC_HVAC: Appearance = Appearance(0x0600);
+    pub const HVAC_THERMOSTAT: Appearance = Appearance(0x0601);
+    pub const HVAC_HUMIDIFIER: Appearance = Appearance(0x0602);
+    pub const HVAC_DEHUMIDIFIER: Appearance = Appearance(0x0603);
+    pub const HVAC_HEATER: Appearance = Appearance(0x0604);
+    pub const HVAC_RADIATOR: Appearance = Appearance(0x0605);
+    pub const HVAC_BOILER: Appearance = Appearance(0x0606);
+    pub const HVAC_HEAT_PUMP: Appearance = Appearance(0x0607);
+    pub const HVAC_INFRARED_HEATER: Appearance = Appearance(0x0608);
+    pub const HVAC_RADIANT_PANEL_HEATER: Appearance = Appearance(0x0609);
+    pub const HVAC_FAN_HEATER: Appearance = Appearance(0x060A);
+    pub const HVAC_AIR_CURTAIN: Appearance = Appearance(0x060B);
+    pub const GENERIC_AIR_CONDITIONING: Appearance = Appearance(0x0640);
+    pub const GENERIC_HUMIDIFIER: Appearance = Appearance(0x0680);
+    pub const GENERIC_HEATING: Appearance = Appearance(0x06C0);
+    pub const HEATING_RADIATOR: Appearance = Appearance(0x06C1);
+    pub const HEATING_BOILER: Appearance = Appearance(0x06C2);
+    pub const HEATING_HEAT_PUMP: Appearance = Appearance(0x06C3);
+    pub const HEATING_INFRARED_HEATER: Appearance = Appearance(0x06C4);
+    pub const HEATING_RADIANT_PANEL_HEATER: Appearance = Appearance(0x06C5);
+    pub const HEATING_FAN_HEATER: Appearance = Appearance(0x06C6);
+    pub const HEATING_AIR_CURTAIN: Appearance = Appearance(0x06C7);
+    pub const GENERIC_ACCESS_CONTROL: Appearance = Appearance(0x0700);
+    pub const ACCESS_CONTROL_ACCESS_DOOR: Appearance = Appearance(0x0701);
+    pub const ACCESS_CONTROL_GARAGE_DOOR: Appearance = Appearance(0x0702);
+    pub const ACCESS_CONTROL_EMERGENCY_EXIT_DOOR: Appearance = Appearance(0x0703);
+    pub const ACCESS_CONTROL_ACCESS_LOCK: Appearance = Appearance(0x0704);
+    pub const ACCESS_CONTROL_ELEVATOR: Appearance = Appearance(0x0705);
+    pub const ACCESS_CONTROL_WINDOW: Appearance = Appearance(0x0706);
+    pub const ACCESS_CONTROL_ENTRANCE_GATE: Appearance = Appearance(0x0707);
+    pub const ACCESS_CONTROL_DOOR_LOCK: Appearance = Appearance(0x0708);
+    pub const ACCESS_CONTROL_LOCKER: Appearance = Appearance(0x0709);
+    pub const GENERIC_MOTORIZED_DEVICE: Appearance = Appearance(0x0740);
+    pub const MOTORIZED_DEVICE_MOTORIZED_GATE: Appearance = Appearance(0x0741);
+    pub const MOTORIZED_DEVICE_AWNING: Appearance = Appearance(0x0742);
+    pub const MOTORIZED_DEVICE_BLINDS_OR_SHADES: Appearance = Appearance(0x0743);
+    pub const MOTORIZED_DEVICE_CURTAINS: Appearance = Appearance(0x0744);
+    pub const MOTORIZED_DEVICE_SCREEN: Appearance = Appearance(0x0745);
+    pub const GENERIC_POWER_DEVICE: Appearance = Appearance(0x0780);
+    pub const POWER_DEVICE_POWER_OUTLET: Appearance = Appearance(0x0781);
+    pub const POWER_DEVICE_POWER_STRIP: Appearance = Appearance(0x0782);
+    pub const POWER_DEVICE_PLUG: Appearance = Appearance(0x0783);
+    pub const POWER_DEVICE_POWER_SUPPLY: Appearance = Appearance(0x0784);
+    pub const POWER_DEVICE_LED_DRIVER: Appearance = Appearance(0x0785);
+    pub const POWER_DEVICE_FLUORESCENT_LAMP_GEAR: Appearance = Appearance(0x0786);
+    pub const POWER_DEVICE_HID_LAMP_GEAR: Appearance = Appearance(0x0787);
+    pub const POWER_DEVICE_CHARGE_CASE: Appearance = Appearance(0x0788);
+    pub const POWER_DEVICE_POWER_BANK: Appearance = Appearance(0x0789);
+    pub const GENERIC_LIGHT_SOURCE: Appearance = Appearance(0x07C0);
+    pub const LIGHT_SOURCE_INCANDESCENT_LIGHT_BULB: Appearance = Appearance(0x07C1);
+    pub const LIGHT_SOURCE_LED_LAMP: Appearance = Appearance(0x07C2);
+    pub const LIGHT_SOURCE_HID_LAMP: Appearance = Appearance(0x07C3);
+    pub const LIGHT_SOURCE_FLUORESCENT_LAMP: Appearance = Appearance(0x07C4);
+    pub const LIGHT_SOURCE_LED_ARRAY: Appearance = Appearance(0x07C5);
+    pub const LIGHT_SOURCE_MULTI_COLOR_LED_ARRAY: Appearance = Appearance(0x07C6);
+    pub const LIGHT_SOURCE_LOW_VOLTAGE_HALOGEN: Appearance = Appearance(0x07C7);
+    pub const LIGHT_SOURCE_OLED: Appearance = Appearance(0x07C8);
+    pub const GENERIC_WINDOW_COVERING: Appearance = Appearance(0x0800);
+    pub const WINDOW_COVERING_WINDOW_SHADES: Appearance = Appearance(0x0801);
+    pub const WINDOW_COVERING_WINDOW_BLINDS: Appearance = Appearance(0x0802);
+    pub const WINDOW_COVERING_WINDOW_AWNING: Appearance = Appearance(0x0803);
+    pub const WINDOW_COVERING_WINDOW_CURTAIN: Appearance = Appearance(0x0804);
+    pub const WINDOW_COVERING_EXTERIOR_SHUTTER: Appearance = Appearance(0x0805);
+    pub const WINDOW_COVERING_EXTERIOR_SCREEN: Appearance = Appearance(0x0806);
+    pub const GENERIC_AUDIO_SINK: Appearance = Appearance(0x0840);
+    pub const AUDIO_SINK_STANDALONE_SPEAKER: Appearance = Appearance(0x0841);
+    pub const AUDIO_SINK_SOUNDBAR: Appearance = Appearance(0x0842);
+    pub const AUDIO_SINK_BOOKSHELF_SPEAKER: Appearance = Appearance(0x0843);
+    pub const AUDIO_SINK_STANDMOUNTED_SPEAKER: Appearance = Appearance(0x0844);
+    pub const AUDIO_SINK_SPEAKERPHONE: Appearance = Appearance(0x0845);
+    pub const GENERIC_AUDIO_SOURCE: Appearance = Appearance(0x0880);
+    pub const AUDIO_SOURCE_MICROPHONE: Appearance = Appearance(0x0881);
+    pub const AUDIO_SOURCE_ALARM: Appearance = Appearance(0x0882);
+    pub const AUDIO_SOURCE_BELL: Appearance = Appearance(0x0883);
+    pub const AUDIO_SOURCE_HORN: Appearance = Appearance(0x0884);
+    pub const AUDIO_SOURCE_BROADCASTING_DEVICE: Appearance = Appearance(0x0885);
+    pub const AUDIO_SOURCE_SERVICE_DESK: Appearance = Appearance(0x0886);
+    pub const AUDIO_SOURCE_KIOSK: Appearance = Appearance(0x0887);
+    pub const AUDIO_SOURCE_BROADCASTING_ROOM: Appearance = Appearance(0x0888);
+    pub const AUDIO_SOURCE_AUDITORIUM: Appearance = Appearance(0x0889);
+    pub const GENERIC_MOTORIZED_VEHICLE: Appearance = Appearance(0x08C0);
+    pub const MOTORIZED_VEHICLE_CAR: Appearance = Appearance(0x08C1);
+    pub const MOTORIZED_VEHICLE_LARGE_GOODS_VEHICLE: Appearance = Appearance(0x08C2);
+    pub const MOTORIZED_VEHICLE_TWO_WHEELED_VEHICLE: Appearance = Appearance(0x08C3);
+    pub const MOTORIZED_VEHICLE_MOTORBIKE: Appearance = Appearance(0x08C4);
+    pub const MOTORIZED_VEHICLE_SCOOTER: Appearance = Appearance(0x08C5);
+    pub const MOTORIZED_VEHICLE_MOPED: Appearance = Appearance(0x08C6);
+    pub const MOTORIZED_VEHICLE_THREE_WHEELED_VEHICLE: Appearance = Appearance(0x08C7);
+    pub const MOTORIZED_VEHICLE_LIGHT_VEHICLE: Appearance = Appearance(0x08C8);
+    pub const MOTORIZED_VEHICLE_QUAD_BIKE: Appearance = Appearance(0x08C9);
+    pub const MOTORIZED_VEHICLE_MINIBUS: Appearance = Appearance(0x08CA);
+    pub const MOTORIZED_VEHICLE_BUS: Appearance = Appearance(0x08CB);
+    pub const MOTORIZED_VEHICLE_TROLLEY: Appearance = Appearance(0x08CC);
+    pub const MOTORIZED_VEHICLE_AGRICULTURAL_VEHICLE: Appearance = Appearance(0x08CD);
+    pub const MOTORIZED_VEHICLE_CAMPER_CARAVAN: Appearance = Appearance(0x08CE);
+    pub const MOTORIZED_VEHICLE_RECREATIONAL_VEHICLE_MOTOR_HOME: Appearance = Appearance(0x08CF);
+    pub const GENERIC_DOMESTIC_APPLIANCE: Appearance = Appearance(0x0900);
+    pub const DOMESTIC_APPLIANCE_REFRIGERATOR: Appearance = Appearance(0x0901);
+    pub const DOMESTIC_APPLIANCE_FREEZER: Appearance = Appearance(0x0902);
+    pub const DOMESTIC_APPLIANCE_OVEN: Appearance = Appearance(0x0903);
+    pub const DOMESTIC_APPLIANCE_MICROWAVE: Appearance = Appearance(0x0904);
+    pub const DOMESTIC_APPLIANCE_TOASTER: Appearance = Appearance(0x0905);
+    pub const DOMESTIC_APPLIANCE_WASHING_MACHINE: Appearance = Appearance(0x0906);
+    pub const DOMESTIC_APPLIANCE_DRYER: Appearance = Appearance(0x0907);
+    pub const DOMESTIC_APPLIANCE_COFFEE_MAKER: Appearance = Appearance(0x0908);
+    pub const DOMESTIC_APPLIANCE_CLOTHES_IRON: Appearance = Appearance(0x0909);
+    pub const DOMESTIC_APPLIANCE_CURLING_IRON: Appearance = Appearance(0x090A);
+    pub const DOMESTIC_APPLIANCE_HAIR_DRYER: Appearance = Appearance(0x090B);
+    pub const DOMESTIC_APPLIANCE_VACUUM_CLEANER: Appearance = Appearance(0x090C);
+    pub const DOMESTIC_APPLIANCE_ROBOTIC_VACUUM_CLEANER: Appearance = Appearance(0x090D);
+    pub const DOMESTIC_APPLIANCE_RICE_COOKER: Appearance = Appearance(0x090E);
+    pub const DOMESTIC_APPLIANCE_CLOTHES_STEAMER: Appearance = Appearance(0x090F);
+    pub const GENERIC_WEARABLE_AUDIO_DEVICE: Appearance = Appearance(0x0940);
+    pub const WEARABLE_AUDIO_DEVICE_EARBUD: Appearance = Appearance(0x0941);
+    pub const WEARABLE_AUDIO_DEVICE_HEADSET: Appearance = Appearance(0x0942);
+    pub const WEARABLE_AUDIO_DEVICE_HEADPHONES: Appearance = Appearance(0x0943);
+    pub const WEARABLE_AUDIO_DEVICE_NECK_BAND: Appearance = Appearance(0x0944);
+    pub const GENERIC_AIRCRAFT: Appearance = Appearance(0x0980);
+    pub const AIRCRAFT_LIGHT_AIRCRAFT: Appearance = Appearance(0x0981);
+    pub const AIRCRAFT_MICROLIGHT: Appearance = Appearance(0x0982);
+    pub const AIRCRAFT_PARAGLIDER: Appearance = Appearance(0x0983);
+    pub const AIRCRAFT_LARGE_PASSENGER_AIRCRAFT: Appearance = Appearance(0x0984);
+    pub const GENERIC_AV_EQUIPMENT: Appearance = Appearance(0x09C0);
+    pub const AV_EQUIPMENT_AMPLIFIER: Appearance = Appearance(0x09C1);
+    pub const AV_EQUIPMENT_RECEIVER: Appearance = Appearance(0x09C2);
+    pub const AV_EQUIPMENT_RADIO: Appearance = Appearance(0x09C3);
+    pub const AV_EQUIPMENT_TUNER: Appearance = Appearance(0x09C4);
+    pub const AV_EQUIPMENT_TURNTABLE: Appearance = Appearance(0x09C5);
+    pub const AV_EQUIPMENT_CD_PLAYER: Appearance = Appearance(0x09C6);
+    pub const AV_EQUIPMENT_DVD_PLAYER: Appearance = Appearance(0x09C7);
+    pub const AV_EQUIPMENT_BLURAY_PLAYER: Appearance = Appearance(0x09C8);
+    pub const AV_EQUIPMENT_OPTICAL_DISC_PLAYER: Appearance = Appearance(0x09C9);
+    pub const AV_EQUIPMENT_SET_TOP_BOX: Appearance = Appearance(0x09CA);
+    pub const GENERIC_DISPLAY_EQUIPMENT: Appearance = Appearance(0x0A00);
+    pub const DISPLAY_EQUIPMENT_TELEVISION: Appearance = Appearance(0x0A01);
+    pub const DISPLAY_EQUIPMENT_MONITOR: Appearance = Appearance(0x0A02);
+    pub const DISPLAY_EQUIPMENT_PROJECTOR: Appearance = Appearance(0x0A03);
+    pub const GENERIC_HEARING_AID: Appearance = Appearance(0x0A40);
+    pub const HEARING_AID_IN_EAR_HEARING_AID: Appearance = Appearance(0x0A41);
+    pub const HEARING_AID_BEHIND_EAR_HEARING_AID: Appearance = Appearance(0x0A42);
+    pub const HEARING_AID_COCHLEAR_IMPLANT: Appearance = Appearance(0x0A43);
+    pub const GENERIC_GAMING: Appearance = Appearance(0x0A80);
+    pub const GAMING_HOME_VIDEO_GAME_CONSOLE: Appearance = Appearance(0x0A81);
+    pub const GAMING_PORTABLE_HANDHELD_CONSOLE: Appearance = Appearance(0x0A82);
+    pub const GENERIC_SIGNAGE: Appearance = Appearance(0x0AC0);
+    pub const SIGNAGE_DIGITAL_SIGNAGE: Appearance = Appearance(0x0AC1);
+    pub const SIGNAGE_ELECTRONIC_LABEL: Appearance = Appearance(0x0AC2);
+    pub const GENERIC_PULSE_OXIMETER: Appearance = Appearance(0x0C40);
+    pub const PULSE_OXIMETER_FINGERTIP: Appearance = Appearance(0x0C41);
+    pub const PULSE_OXIMETER_WRIST_WORN: Appearance = Appearance(0x0C42);
+    pub const GENERIC_WEIGHT_SCALE: Appearance = Appearance(0x0C80);
+    pub const GENERIC_PERSONAL_MOBILITY_DEVICE: Appearance = Appearance(0x0CC0);
+    pub const PERSONAL_MOBILITY_DEVICE_POWERED_WHEELCHAIR: Appearance = Appearance(0x0CC1);
+    pub const PERSONAL_MOBILITY_DEVICE_MOBILITY_SCOOTER: Appearance = Appearance(0x0CC2);
+    pub const GENERIC_CONTINUOUS_GLUCOSE_MONITOR: Appearance = Appearance(0x0D00);
+    pub const GENERIC_INSULIN_PUMP: Appearance = Appearance(0x0D40);
+    pub const INSULIN_PUMP_DURABLE_PUMP: Appearance = Appearance(0x0D41);
+    pub const INSULIN_PUMP_PATCH_PUMP: Appearance = Appearance(0x0D44);
+    pub const INSULIN_PUMP_PEN: Appearance = Appearance(0x0D48);
+    pub const GENERIC_MEDICATION_DELIVERY: Appearance = Appearance(0x0D80);
+    pub const GENERIC_SPIROMETER: Appearance = Appearance(0x0DC0);
+    pub const SPIROMETER_HANDHELD: Appearance = Appearance(0x0DC1);
+    pub const GENERIC_OUTDOOR_SPORTS_ACTIVITY: Appearance = Appearance(0x1440);
+    pub const OUTDOOR_SPORTS_ACTIVITY_LOCATION_DISPLAY: Appearance = Appearance(0x1441);
+    pub const OUTDOOR_SPORTS_ACTIVITY_LOCATION_AND_NAVIGATION_DISPLAY: Appearance = Appearance(0x1442);
+    pub const OUTDOOR_SPORTS_ACTIVITY_LOCATION_POD: Appearance = Appearance(0x1443);
+    pub const OUTDOOR_SPORTS_ACTIVITY_LOCATION_AND_NAVIGATION_POD: Appearance = Appearance(0x1444);
+    pub const GENERIC_INDUSTRIAL_MEASUREMENT_DEVICE: Appearance = Appearance(0x1480);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_TORQUE_TESTING_DEVICE: Appearance = Appearance(0x1481);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_CALIPER: Appearance = Appearance(0x1482);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_DIAL_INDICATOR: Appearance = Appearance(0x1483);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_MICROMETER: Appearance = Appearance(0x1484);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_HEIGHT_GAUGE: Appearance = Appearance(0x1485);
+    pub const INDUSTRIAL_MEASUREMENT_DEVICE_FORCE_GAUGE: Appearance = Appearance(0x1486);
+    pub const GENERIC_INDUSTRIAL_TOOLS: Appearance = Appearance(0x14C0);
+    pub const INDUSTRIAL_TOOLS_MACHINE_TOOL_HOLDER: Appearance = Appearance(0x14C1);
+    pub const INDUSTRIAL_TOOLS_GENERIC_CLAMPING_DEVICE: Appearance = Appearance(0x14C2);
+    pub const INDUSTRIAL_TOOLS_CLAMPING_JAWS_JAW_CHUCK: Appearance = Appearance(0x14C3);
+    pub const INDUSTRIAL_TOOLS_CLAMPING_COLLET_CHUCK: Appearance = Appearance(0x14C4);
+    pub const INDUSTRIAL_TOOLS_CLAMPING_MANDREL: Appearance = Appearance(0x14C5);
+    pub const INDUSTRIAL_TOOLS_VISE: Appearance = Appearance(0x14C6);
+    pub const INDUSTRIAL_TOOLS_ZERO_POINT_CLAMPING_SYSTEM: Appearance = Appearance(0x14C7);
+    pub const INDUSTRIAL_TOOLS_TORQUE_WRENCH: Appearance = Appearance(0x14C8);
+    pub const INDUSTRIAL_TOOLS_TORQUE_SCREWDRIVER: Appearance = Appearance(0x14C9);
+
+    pub const fn new(category: u16, subcategory: u8) -> Self {
+        Appearance((category << 6) | (subcategory as u16 & 0x3f))
+    }
+
+    pub const fn category(self) -> u16 {
+        self.0 >> 6
+    }
+
+    pub const fn subcategory(self) -> u8 {
+        (self.0 & 0x3f) as u8
+    }
+
+    pub const fn from_u16(value: u16) -> Self {
+        Appearance(value)
+    }
+
+    pub const fn to_u16(self) -> u16 {
+        self.0
+    }
+}
+
+impl From<u16> for Appearance {
+    fn from(value: u16) -> Self {
+        Appearance(value)
+    }
+}
+
+impl From<Appearance> for u16 {
+    fn from(value: Appearance) -> Self {
+        value.0
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub struct GattError(NonZeroU16);
 
@@ -730,3 +1326,138 @@ hci_status_codes! {
     /// Connection Failed to be Established
     (CONN_FAILED_TO_BE_ESTABLISHED, raw::BLE_HCI_CONN_FAILED_TO_BE_ESTABLISHED, "Connection Failed to be Established");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(min_interval_us: u32, max_interval_us: u32, latency: u16, supervision_timeout_ms: u32) -> ConnParams {
+        ConnParams {
+            min_interval_us,
+            max_interval_us,
+            latency,
+            supervision_timeout_ms,
+        }
+    }
+
+    fn raw_params(params: &ConnParams) -> (u16, u16, u16, u16) {
+        let raw = unwrap!(params.to_raw());
+        (
+            raw.min_conn_interval,
+            raw.max_conn_interval,
+            raw.slave_latency,
+            raw.conn_sup_timeout,
+        )
+    }
+
+    #[test]
+    fn conn_params_rounding() {
+        assert_eq!(raw_params(&params(7_500, 15_000, 0, 4_000)), (6, 12, 0, 400));
+        // 8.1ms is 6.48 units, 8.2ms is 6.56 units.
+        assert_eq!(raw_params(&params(8_100, 8_200, 0, 4_004)), (6, 7, 0, 400));
+        assert_eq!(raw_params(&params(8_125, 8_125, 0, 4_005)), (7, 7, 0, 401));
+        // 7ms rounds up to the 7.5ms minimum.
+        assert_eq!(params(7_000, 7_000, 0, 100).validate(), Ok(()));
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn conn_params_from_durations() {
+        use embassy_time::Duration;
+
+        let params = ConnParams::new(
+            Duration::from_micros(7_500),
+            Duration::from_millis(15),
+            2,
+            Duration::from_secs(4),
+        );
+        assert_eq!(params, self::params(7_500, 15_000, 2, 4_000));
+        assert_eq!(raw_params(&params), (6, 12, 2, 400));
+    }
+
+    #[test]
+    fn conn_params_from_raw() {
+        let raw = raw::ble_gap_conn_params_t {
+            min_conn_interval: 6,
+            max_conn_interval: 3200,
+            slave_latency: 3,
+            conn_sup_timeout: 3200,
+        };
+        assert_eq!(ConnParams::from_raw(&raw), params(7_500, 4_000_000, 3, 32_000));
+    }
+
+    #[test]
+    fn conn_params_presets() {
+        for preset in [ConnParams::LOW_POWER, ConnParams::BALANCED, ConnParams::HIGH_THROUGHPUT] {
+            assert_eq!(preset.validate(), Ok(()));
+            assert_eq!(ConnParams::from_raw(&unwrap!(preset.to_raw())), preset);
+        }
+        assert_eq!(raw_params(&ConnParams::BALANCED), (40, 200, 0, 400));
+        assert_eq!(ConnParams::default(), ConnParams::BALANCED);
+    }
+
+    #[test]
+    fn conn_params_range_limits() {
+        assert_eq!(params(7_500, 4_000_000, 0, 32_000).validate(), Ok(()));
+        assert_eq!(
+            params(6_000, 10_000, 0, 4_000).validate(),
+            Err(InvalidConnParams::IntervalOutOfRange)
+        );
+        assert_eq!(
+            params(10_000, 4_001_250, 0, 32_000).validate(),
+            Err(InvalidConnParams::IntervalOutOfRange)
+        );
+        assert_eq!(
+            params(u32::MAX, u32::MAX, 0, 32_000).validate(),
+            Err(InvalidConnParams::IntervalOutOfRange)
+        );
+        assert_eq!(
+            params(20_000, 10_000, 0, 4_000).validate(),
+            Err(InvalidConnParams::IntervalOrder)
+        );
+        assert_eq!(params(7_500, 7_500, 499, 32_000).validate(), Ok(()));
+        assert_eq!(
+            params(7_500, 7_500, 500, 32_000).validate(),
+            Err(InvalidConnParams::LatencyOutOfRange)
+        );
+        assert_eq!(params(7_500, 7_500, 0, 100).validate(), Ok(()));
+        assert_eq!(
+            params(7_500, 7_500, 0, 90).validate(),
+            Err(InvalidConnParams::TimeoutOutOfRange)
+        );
+        assert_eq!(
+            params(7_500, 7_500, 0, 32_010).validate(),
+            Err(InvalidConnParams::TimeoutOutOfRange)
+        );
+        assert_eq!(
+            params(7_500, 7_500, 0, 90).to_raw().map(|_| ()),
+            Err(InvalidConnParams::TimeoutOutOfRange)
+        );
+    }
+
+    #[test]
+    fn conn_params_supervision_timeout() {
+        // The timeout must be above (1 + latency) * max_interval * 2.
+        assert_eq!(
+            params(250_000, 250_000, 0, 500).validate(),
+            Err(InvalidConnParams::TimeoutTooShort)
+        );
+        assert_eq!(params(250_000, 250_000, 0, 510).validate(), Ok(()));
+        assert_eq!(
+            params(250_000, 250_000, 1, 1_000).validate(),
+            Err(InvalidConnParams::TimeoutTooShort)
+        );
+        assert_eq!(params(250_000, 250_000, 1, 1_010).validate(), Ok(()));
+        // Only the maximum interval counts.
+        assert_eq!(
+            params(7_500, 250_000, 0, 500).validate(),
+            Err(InvalidConnParams::TimeoutTooShort)
+        );
+        // The rule applies to the rounded values: 251.2ms rounds to 251.25ms and 504ms to 500ms,
+        // below the 502.5ms limit.
+        assert_eq!(
+            params(251_200, 251_200, 0, 504).validate(),
+            Err(InvalidConnParams::TimeoutTooShort)
+        );
+    }
+}
@@ -42,6 +42,10 @@ pub struct Softdevice {
     pub(crate) att_mtu: u16,
     #[cfg(feature = "ble-l2cap")]
     pub(crate) l2cap_rx_mps: u16,
+    #[cfg(feature = "s140")]
+    pub(crate) event_length: u16,
+    #[cfg(any(feature = "ble-gatt", feature = "ble-l2cap", feature = "s140"))]
+    pub(crate) conn_profiles: &'static [ConnProfile],
 }
 
 /// Softdevice configuration.
@@ -63,6 +67,91 @@ pub struct Config {
     pub gap_car_incl: Option<raw::ble_gap_cfg_car_incl_cfg_t>,
     pub gatts_service_changed: Option<raw::ble_gatts_cfg_service_changed_t>,
     pub gatts_attr_tab_size: Option<raw::ble_gatts_cfg_attr_tab_size_t>,
+    /// Additional connection profiles, selected with [`ConnProfileId::new`].
+    ///
+    /// The `conn_*` fields above make up the default profile, [`ConnProfileId::DEFAULT`].
+    pub conn_profiles: &'static [ConnProfile],
+}
+
+/// Per-connection configuration, see [`Config::conn_profiles`].
+///
+/// The softdevice reserves RAM for `conn_gap.conn_count` connections with this profile.
+/// Fields set to None will use a default configuration.
+#[derive(Default, Clone, Copy)]
+pub struct ConnProfile {
+    pub conn_gap: Option<raw::ble_gap_conn_cfg_t>,
+    pub conn_gattc: Option<raw::ble_gattc_conn_cfg_t>,
+    pub conn_gatts: Option<raw::ble_gatts_conn_cfg_t>,
+    pub conn_gatt: Option<raw::ble_gatt_conn_cfg_t>,
+    #[cfg(feature = "ble-l2cap")]
+    pub conn_l2cap: Option<raw::ble_l2cap_conn_cfg_t>,
+}
+
+impl ConnProfile {
+    #[cfg(feature = "ble-gatt")]
+    fn att_mtu(&self) -> u16 {
+        self.conn_gatt
+            .map(|x| x.att_mtu)
+            .unwrap_or(raw::BLE_GATT_ATT_MTU_DEFAULT as u16)
+    }
+
+    #[cfg(feature = "ble-l2cap")]
+    fn l2cap_rx_mps(&self) -> u16 {
+        self.conn_l2cap
+            .map(|x| x.rx_mps)
+            .unwrap_or(raw::BLE_L2CAP_MPS_MIN as u16)
+    }
+
+    #[cfg(feature = "s140")]
+    fn event_length(&self) -> u16 {
+        self.conn_gap
+            .map(|x| x.event_length)
+            .unwrap_or(raw::BLE_GAP_EVENT_LENGTH_DEFAULT as u16)
+    }
+
+    /// Profile for `conn_count` connections on the coded phy.
+    ///
+    /// The default event length is too short for the coded phy, so connecting or advertising on it
+    /// needs a profile with at least a 7.5ms event length.
+    #[cfg(feature = "s140")]
+    pub const fn long_range(conn_count: u8) -> Self {
+        Self {
+            conn_gap: Some(raw::ble_gap_conn_cfg_t {
+                conn_count,
+                event_length: raw::BLE_GAP_EVENT_LENGTH_CODED_PHY_MIN as u16,
+            }),
+            conn_gattc: None,
+            conn_gatts: None,
+            conn_gatt: None,
+            #[cfg(feature = "ble-l2cap")]
+            conn_l2cap: None,
+        }
+    }
+}
+
+/// Selects the connection profile used for a connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnProfileId(u8);
+
+impl ConnProfileId {
+    /// The profile made up of the `conn_*` fields of [`Config`].
+    pub const DEFAULT: Self = Self(0);
+
+    /// The profile at `index` in [`Config::conn_profiles`].
+    ///
+    /// Panics if `index` is 254 or more, there can't be that many profiles.
+    pub const fn new(index: u8) -> Self {
+        core::assert!(
+            index < u8::MAX - APP_CONN_CFG_TAG,
+            "connection profile index out of range"
+        );
+        Self(index + 1)
+    }
+
+    pub(crate) fn conn_cfg_tag(self) -> u8 {
+        APP_CONN_CFG_TAG + self.0
+    }
 }
 
 const APP_CONN_CFG_TAG: u8 = 1;
@@ -75,6 +164,26 @@ fn get_app_ram_base() -> u32 {
     ptr::addr_of!(__sdata) as u32
 }
 
+// Total number of connections allowed by a role count config.
+fn conn_count(role_count: raw::ble_gap_cfg_role_count_t) -> usize {
+    #[cfg(any(feature = "s112", feature = "s113"))]
+    return role_count.periph_role_count as usize;
+    #[cfg(feature = "s122")]
+    return role_count.central_role_count as usize;
+    #[cfg(any(feature = "s132", feature = "s140"))]
+    return role_count.periph_role_count as usize + role_count.central_role_count as usize;
+}
+
+// Total number of connections allowed by the softdevice's default role count config.
+fn default_conn_count() -> usize {
+    #[cfg(any(feature = "s112", feature = "s113"))]
+    return raw::BLE_GAP_ROLE_COUNT_PERIPH_DEFAULT as usize;
+    #[cfg(feature = "s122")]
+    return raw::BLE_GAP_ROLE_COUNT_CENTRAL_DEFAULT as usize;
+    #[cfg(any(feature = "s132", feature = "s140"))]
+    return (raw::BLE_GAP_ROLE_COUNT_PERIPH_DEFAULT + raw::BLE_GAP_ROLE_COUNT_CENTRAL_DEFAULT) as usize;
+}
+
 fn cfg_set(id: u32, cfg: &raw::ble_cfg_t) {
     let app_ram_base = get_app_ram_base();
     let ret = unsafe { raw::sd_ble_cfg_set(id, cfg, app_ram_base) };
@@ -85,6 +194,74 @@ fn cfg_set(id: u32, cfg: &raw::ble_cfg_t) {
     }
 }
 
+// Set the connection configs of a profile for `conn_cfg_tag`.
+fn set_conn_cfg(conn_cfg_tag: u8, profile: &ConnProfile) {
+    // Set at least one GAP config so the conn_cfg_tag is usable.
+    // If you set none, it seems the softdevice won't let you use it, requiring a conn_cfg_tag of 0 (raw::BLE_CONN_CFG_TAG_DEFAULT) instead.
+    let val = profile.conn_gap.unwrap_or(raw::ble_gap_conn_cfg_t {
+        conn_count: raw::BLE_GAP_CONN_COUNT_DEFAULT as u8,
+        event_length: raw::BLE_GAP_EVENT_LENGTH_DEFAULT as u16,
+    });
+    cfg_set(
+        raw::BLE_CONN_CFGS_BLE_CONN_CFG_GAP,
+        &raw::ble_cfg_t {
+            conn_cfg: raw::ble_conn_cfg_t {
+                conn_cfg_tag,
+                params: raw::ble_conn_cfg_t__bindgen_ty_1 { gap_conn_cfg: val },
+            },
+        },
+    );
+
+    if let Some(val) = profile.conn_gatt {
+        cfg_set(
+            raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATT,
+            &raw::ble_cfg_t {
+                conn_cfg: raw::ble_conn_cfg_t {
+                    conn_cfg_tag,
+                    params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatt_conn_cfg: val },
+                },
+            },
+        );
+    }
+
+    if let Some(val) = profile.conn_gattc {
+        cfg_set(
+            raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATTC,
+            &raw::ble_cfg_t {
+                conn_cfg: raw::ble_conn_cfg_t {
+                    conn_cfg_tag,
+                    params: raw::ble_conn_cfg_t__bindgen_ty_1 { gattc_conn_cfg: val },
+                },
+            },
+        );
+    }
+
+    if let Some(val) = profile.conn_gatts {
+        cfg_set(
+            raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATTS,
+            &raw::ble_cfg_t {
+                conn_cfg: raw::ble_conn_cfg_t {
+                    conn_cfg_tag,
+                    params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatts_conn_cfg: val },
+                },
+            },
+        );
+    }
+
+    #[cfg(feature = "ble-l2cap")]
+    if let Some(val) = profile.conn_l2cap {
+        cfg_set(
+            raw::BLE_CONN_CFGS_BLE_CONN_CFG_L2CAP,
+            &raw::ble_cfg_t {
+                conn_cfg: raw::ble_conn_cfg_t {
+                    conn_cfg_tag,
+                    params: raw::ble_conn_cfg_t__bindgen_ty_1 { l2cap_conn_cfg: val },
+                },
+            },
+        );
+    }
+}
+
 static ENABLED: AtomicBool = AtomicBool::new(false);
 static mut SOFTDEVICE: MaybeUninit<Softdevice> = MaybeUninit::uninit();
 
@@ -112,69 +289,23 @@ impl Softdevice {
 
         let app_ram_base = get_app_ram_base();
 
-        // Set at least one GAP config so conn_cfg_tag 1 (APP_CONN_CFG_TAG) is usable.
-        // If you set none, it seems the softdevice won't let you use it, requiring a conn_cfg_tag of 0 (raw::BLE_CONN_CFG_TAG_DEFAULT) instead.
-        let val = config.conn_gap.unwrap_or(raw::ble_gap_conn_cfg_t {
-            conn_count: raw::BLE_GAP_CONN_COUNT_DEFAULT as u8,
-            event_length: raw::BLE_GAP_EVENT_LENGTH_DEFAULT as u16,
-        });
-        cfg_set(
-            raw::BLE_CONN_CFGS_BLE_CONN_CFG_GAP,
-            &raw::ble_cfg_t {
-                conn_cfg: raw::ble_conn_cfg_t {
-                    conn_cfg_tag: APP_CONN_CFG_TAG,
-                    params: raw::ble_conn_cfg_t__bindgen_ty_1 { gap_conn_cfg: val },
-                },
-            },
+        let default_profile = ConnProfile {
+            conn_gap: config.conn_gap,
+            conn_gattc: config.conn_gattc,
+            conn_gatts: config.conn_gatts,
+            conn_gatt: config.conn_gatt,
+            #[cfg(feature = "ble-l2cap")]
+            conn_l2cap: config.conn_l2cap,
+        };
+        assert!(
+            config.conn_profiles.len() < (u8::MAX - APP_CONN_CFG_TAG) as usize,
+            "too many connection profiles"
         );
-
-        if let Some(val) = config.conn_gatt {
-            cfg_set(
-                raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATT,
-                &raw::ble_cfg_t {
-                    conn_cfg: raw::ble_conn_cfg_t {
-                        conn_cfg_tag: APP_CONN_CFG_TAG,
-                        params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatt_conn_cfg: val },
-                    },
-                },
-            );
-        }
-
-        if let Some(val) = config.conn_gattc {
-            cfg_set(
-                raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATTC,
-                &raw::ble_cfg_t {
-                    conn_cfg: raw::ble_conn_cfg_t {
-                        conn_cfg_tag: APP_CONN_CFG_TAG,
-                        params: raw::ble_conn_cfg_t__bindgen_ty_1 { gattc_conn_cfg: val },
-                    },
-                },
-            );
-        }
-
-        if let Some(val) = config.conn_gatts {
-            cfg_set(
-                raw::BLE_CONN_CFGS_BLE_CONN_CFG_GATTS,
-                &raw::ble_cfg_t {
-                    conn_cfg: raw::ble_conn_cfg_t {
-                        conn_cfg_tag: APP_CONN_CFG_TAG,
-                        params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatts_conn_cfg: val },
-                    },
-                },
-            );
-        }
-
-        #[cfg(feature = "ble-l2cap")]
-        if let Some(val) = config.conn_l2cap {
-            cfg_set(
-                raw::BLE_CONN_CFGS_BLE_CONN_CFG_L2CAP,
-                &raw::ble_cfg_t {
-                    conn_cfg: raw::ble_conn_cfg_t {
-                        conn_cfg_tag: APP_CONN_CFG_TAG,
-                        params: raw::ble_conn_cfg_t__bindgen_ty_1 { l2cap_conn_cfg: val },
-                    },
-                },
-            );
+        for (i, profile) in core::iter::once(&default_profile)
+            .chain(config.conn_profiles)
+            .enumerate()
+        {
+            set_conn_cfg(APP_CONN_CFG_TAG + i as u8, profile);
         }
 
         if let Some(val) = config.common_vs_uuid {
@@ -186,6 +317,15 @@ impl Softdevice {
             );
         }
 
+        let conn_count = config.gap_role_count.map_or(default_conn_count(), conn_count);
+        if conn_count > crate::ble::CONNS_MAX {
+            panic!(
+                "gap_role_count allows {:?} connections, but at most {:?} are supported. Enable a larger conns-max-* feature.",
+                conn_count,
+                crate::ble::CONNS_MAX
+            );
+        }
+
         if let Some(val) = config.gap_role_count {
             cfg_set(
                 raw::BLE_GAP_CFGS_BLE_GAP_CFG_ROLE_COUNT,
@@ -265,16 +405,13 @@ impl Softdevice {
         }
 
         #[cfg(feature = "ble-gatt")]
-        let att_mtu = config
-            .conn_gatt
-            .map(|x| x.att_mtu)
-            .unwrap_or(raw::BLE_GATT_ATT_MTU_DEFAULT as u16);
+        let att_mtu = default_profile.att_mtu();
 
         #[cfg(feature = "ble-l2cap")]
-        let l2cap_rx_mps = config
-            .conn_l2cap
-            .map(|x| x.rx_mps)
-            .unwrap_or(raw::BLE_L2CAP_MPS_MIN as u16);
+        let l2cap_rx_mps = default_profile.l2cap_rx_mps();
+
+        #[cfg(feature = "s140")]
+        let event_length = default_profile.event_length();
 
         let sd = Softdevice {
             _private: PhantomData,
@@ -284,6 +421,12 @@ impl Softdevice {
 
             #[cfg(feature = "ble-l2cap")]
             l2cap_rx_mps,
+
+            #[cfg(feature = "s140")]
+            event_length,
+
+            #[cfg(any(feature = "ble-gatt", feature = "ble-l2cap", feature = "s140"))]
+            conn_profiles: config.conn_profiles,
         };
 
         unsafe {
@@ -301,6 +444,48 @@ impl Softdevice {
         &*(&*(&raw const SOFTDEVICE)).as_ptr()
     }
 
+    /// The profile selected by `profile`, or `None` for the default profile.
+    ///
+    /// An id past the end of [`Config::conn_profiles`] can't have been used to make a connection,
+    /// the softdevice rejects its tag. It is treated as the default profile.
+    #[cfg(any(feature = "ble-gatt", feature = "ble-l2cap", feature = "s140"))]
+    fn conn_profile(&self, profile: ConnProfileId) -> Option<&ConnProfile> {
+        let i = profile.0.checked_sub(1)?;
+        let p = self.conn_profiles.get(i as usize);
+        if p.is_none() {
+            warn!("unknown connection profile {:?}", i);
+        }
+        p
+    }
+
+    /// Maximum ATT MTU for connections using `profile`.
+    #[cfg(feature = "ble-gatt")]
+    pub(crate) fn att_mtu_for(&self, profile: ConnProfileId) -> u16 {
+        match self.conn_profile(profile) {
+            None => self.att_mtu,
+            Some(p) => p.att_mtu(),
+        }
+    }
+
+    /// L2CAP RX MPS for connections using `profile`.
+    #[cfg(feature = "ble-l2cap")]
+    pub(crate) fn l2cap_rx_mps_for(&self, profile: ConnProfileId) -> u16 {
+        match self.conn_profile(profile) {
+            None => self.l2cap_rx_mps,
+            Some(p) => p.l2cap_rx_mps(),
+        }
+    }
+
+    /// Whether connections using `profile` can run on the coded phy.
+    #[cfg(feature = "s140")]
+    pub(crate) fn supports_coded_phy(&self, profile: ConnProfileId) -> bool {
+        let event_length = match self.conn_profile(profile) {
+            None => self.event_length,
+            Some(p) => p.event_length(),
+        };
+        event_length >= raw::BLE_GAP_EVENT_LENGTH_CODED_PHY_MIN as u16
+    }
+
     /// Runs the softdevice event handling loop.
     ///
     /// It must be called in its own async task after enabling the softdevice
@@ -3,7 +3,9 @@ use core::mem;
 use core::mem::MaybeUninit;
 use core::ptr::NonNull;
 
-use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(not(any(feature = "usable-from-interrupts", test)))]
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::signal::Signal;
 
@@ -11,9 +13,10 @@ use crate::util::OnDrop;
 
 /// Utility to call a closure across tasks.
 pub struct Portal<T> {
-    #[cfg(feature = "usable-from-interrupts")]
+    // `ThreadModeRawMutex` only exists on Cortex-M, so host builds for unit tests use a critical section.
+    #[cfg(any(feature = "usable-from-interrupts", test))]
     state: Mutex<CriticalSectionRawMutex, RefCell<State<T>>>,
-    #[cfg(not(feature = "usable-from-interrupts"))]
+    #[cfg(not(any(feature = "usable-from-interrupts", test)))]
     state: Mutex<ThreadModeRawMutex, RefCell<State<T>>>,
 }
 
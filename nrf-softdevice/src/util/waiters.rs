@@ -0,0 +1,95 @@
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+enum Slot {
+    Free,
+    Taken(Option<Waker>),
+}
+
+const FREE: Slot = Slot::Free;
+
+/// Futures waiting for a condition to become true, up to `N` at a time.
+///
+/// Unlike `MultiWakerRegistration`, each waiting future keeps its own slot until it's dropped, so
+/// waiters never evict each other's wakers. Waiting while all `N` slots are in use panics.
+pub struct Waiters<const N: usize> {
+    slots: Mutex<CriticalSectionRawMutex, RefCell<[Slot; N]>>,
+}
+
+impl<const N: usize> Waiters<N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Mutex::new(RefCell::new([FREE; N])),
+        }
+    }
+
+    /// Wake all the waiting futures, so they check their condition again.
+    pub fn wake(&self) {
+        self.slots.lock(|slots| {
+            for slot in slots.borrow_mut().iter_mut() {
+                if let Slot::Taken(waker) = slot {
+                    if let Some(waker) = waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+        })
+    }
+
+    /// Wait until `f` returns `Ready`. `f` is called on each poll, after being woken by [`wake`](Self::wake).
+    pub fn wait<T, F: FnMut() -> Poll<T> + Unpin>(&self, f: F) -> Wait<'_, N, F> {
+        Wait {
+            waiters: self,
+            slot: None,
+            f,
+        }
+    }
+}
+
+/// Future returned by [`Waiters::wait`].
+pub struct Wait<'a, const N: usize, F> {
+    waiters: &'a Waiters<N>,
+    slot: Option<usize>,
+    f: F,
+}
+
+impl<const N: usize, T, F: FnMut() -> Poll<T> + Unpin> Future for Wait<'_, N, F> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = self.get_mut();
+        if let Poll::Ready(res) = (this.f)() {
+            return Poll::Ready(res);
+        }
+
+        this.waiters.slots.lock(|slots| {
+            let mut slots = slots.borrow_mut();
+            let index = match this.slot {
+                Some(index) => index,
+                None => {
+                    let index = slots
+                        .iter()
+                        .position(|slot| matches!(slot, Slot::Free))
+                        .expect("too many tasks waiting at once");
+                    this.slot = Some(index);
+                    index
+                }
+            };
+            slots[index] = Slot::Taken(Some(cx.waker().clone()));
+        });
+        Poll::Pending
+    }
+}
+
+impl<const N: usize, F> Drop for Wait<'_, N, F> {
+    fn drop(&mut self) {
+        if let Some(index) = self.slot {
+            self.waiters.slots.lock(|slots| slots.borrow_mut()[index] = Slot::Free);
+        }
+    }
+}